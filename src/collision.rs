@@ -1,18 +1,197 @@
 use bevy::prelude::*;
+use bevy::utils::{HashMap, Instant};
+use bevy::transform::systems::{propagate_transforms, sync_simple_transforms};
+use bevy::transform::TransformSystem;
+
+mod broad_phase;
+mod ccd;
+mod character;
+mod compound;
+mod debug;
+mod diagnostics;
+mod distance;
+mod dynamics;
+mod epa;
+#[cfg(test)]
+mod fixtures;
+mod hull;
+mod interpolation;
+mod manifold;
+mod primitive;
+mod query;
+mod response;
+mod sleep;
+mod terrain;
+pub use broad_phase::*;
+pub use ccd::*;
+pub use character::*;
+pub use compound::*;
+pub use debug::*;
+pub use diagnostics::*;
+pub use distance::*;
+pub use dynamics::*;
+pub use epa::*;
+pub use hull::*;
+pub use interpolation::*;
+pub use manifold::*;
+pub use primitive::*;
+pub use query::*;
+pub use response::*;
+pub use sleep::*;
+pub use terrain::*;
+
+//how much closer to the origin each new support point has to get for gjk to keep going
+pub const GJK_TOLERANCE: f32 = 0.0001;
+pub const GJK_MAX_ITERATIONS: u32 = 64;
 
 //origin will be obtained from a Transform query
 //#[derive(Component, PartialEq)]
-#[derive(Component)]
+#[derive(Component, Clone)]
 pub struct Collider {
     shape: Shapes,
     local_points: Vec<Vec3>,
     transformed_points: Vec<Vec3>,
+    //uniform scale taken from the GlobalTransform, rounded shapes multiply their radius by it
+    scale: f32,
 }
 
 //#[derive(PartialEq)]
+//the analytic shapes keep their centre and axes in local_points so the transform gets applied the same way as a polyhedron's points
+#[derive(Clone)]
 pub enum Shapes {
     Sphere,
     Polyhedron,
+    //[top of segment, bottom of segment, (radius, 0, 0)]
+    Capsule,
+    //[centre, (half x, 0, 0), (0, half y, 0), (0, 0, half z)]
+    Cuboid,
+    //[centre, (0, half height, 0), (radius, 0, 0), (0, 0, radius)]
+    Cylinder,
+    //same as the cylinder, the point is at the top and the base is at the bottom
+    Cone,
+    //several convex shapes moving together, local_points is empty and each part keeps its own points
+    Compound(Vec<CompoundPart>),
+    //[point on the surface, point one unit above it], everything below the surface is solid
+    HalfSpace,
+    //[origin, (0, 1, 0), then the grid of points one row after another], the first two give which way is up
+    Heightfield { rows: usize, columns: usize },
+}
+
+/**
+ * Optional companion to Collider that decides which other colliders it gets tested against.
+ * memberships are the layers this collider is on and filters are the layers it wants to collide with.
+ * A pair is only tested when each one's memberships are in the other's filters.
+ * Colliders without this component are on every layer and collide with everything.
+ */
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct CollisionLayers {
+    pub memberships: u32,
+    pub filters: u32,
+}
+
+impl CollisionLayers {
+    pub const ALL: u32 = u32::MAX;
+    pub const NONE: u32 = 0;
+
+    pub fn new(memberships: u32, filters: u32) -> Self {
+        return Self { memberships, filters };
+    }
+
+    pub fn interacts_with(&self, other: &CollisionLayers) -> bool {
+        return (self.memberships & other.filters) != 0 && (other.memberships & self.filters) != 0;
+    }
+}
+
+impl Default for CollisionLayers {
+    fn default() -> Self {
+        return Self::new(Self::ALL, Self::ALL);
+    }
+}
+
+/**
+ * How a collider moves, colliders without this component are Static.
+ * Static colliders never move so pairs of them are never tested and their points are only recomputed when their transform changes.
+ * Dynamic colliders get pushed out of whatever they overlap.
+ * Kinematic colliders are moved by game code, they push dynamic colliders out of the way but never get pushed themselves.
+ */
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BodyType {
+    #[default]
+    Static,
+    Dynamic,
+    Kinematic,
+}
+
+//sent the first frame two colliders touch, contact.normal points from e1 towards e2
+#[derive(Event)]
+pub struct CollisionStarted {
+    pub e1: Entity,
+    pub e2: Entity,
+    pub contact: Contact,
+}
+
+//sent every frame after that while they keep touching
+#[derive(Event)]
+pub struct CollisionContinued {
+    pub e1: Entity,
+    pub e2: Entity,
+    pub contact: Contact,
+}
+
+//sent the first frame they stop touching, also sent when one of them is despawned
+#[derive(Event)]
+pub struct CollisionEnded {
+    pub e1: Entity,
+    pub e2: Entity,
+}
+
+/**
+ * Every pair of entities touching this frame.
+ * Pairs are stored with the smaller entity first and the contact is worked out in that order.
+ * Each pair also has a manifold with all of its contact points, kept from frame to frame while the pair stays touching.
+ */
+#[derive(Resource, Default)]
+pub struct Contacts {
+    pairs: HashMap<(Entity, Entity), Contact>,
+    manifolds: HashMap<(Entity, Entity), ContactManifold>,
+}
+
+impl Contacts {
+    //the contact's normal points from e1 towards e2 whichever order they are passed in
+    pub fn get(&self, e1: Entity, e2: Entity) -> Option<Contact> {
+        if e1 < e2 {
+            return self.pairs.get(&(e1, e2)).copied();
+        }
+        return self.pairs.get(&(e2, e1)).map(|contact| contact.flipped());
+    }
+
+    //the manifold's normal points from e1 towards e2 whichever order they are passed in
+    pub fn manifold(&self, e1: Entity, e2: Entity) -> Option<ContactManifold> {
+        if e1 < e2 {
+            return self.manifolds.get(&(e1, e2)).cloned();
+        }
+        return self.manifolds.get(&(e2, e1)).map(|manifold| manifold.flipped());
+    }
+
+    pub fn contains(&self, e1: Entity, e2: Entity) -> bool {
+        return self.pairs.contains_key(&(e1.min(e2), e1.max(e2)));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, Entity, &Contact)> {
+        return self.pairs.iter().map(|(&(e1, e2), contact)| (e1, e2, contact));
+    }
+
+    //every entity touching the given one
+    pub fn touching(&self, entity: Entity) -> impl Iterator<Item = Entity> + '_ {
+        return self.pairs.keys().filter_map(move |&(e1, e2)| {
+            if e1 == entity {
+                return Some(e2);
+            } else if e2 == entity {
+                return Some(e1);
+            }
+            return None;
+        });
+    }
 }
 
 impl Collider {
@@ -22,7 +201,7 @@ impl Collider {
                 let radius = self.transformed_points[0];
                 let centre = radius - self.local_points[0];
 
-                return centre + (d * self.local_points[0][0]);
+                return centre + (d * self.local_points[0][0] * self.scale);
             },
             Shapes::Polyhedron => {
                 let mut max_dot = std::f32::NEG_INFINITY;
@@ -47,15 +226,74 @@ impl Collider {
                 if not is there a way to link the Transform and the boudning shape?
                 I think I will have to do this as the transform will allow me to rotate the shapes and stuff
                  */
-            }
+            },
+            Shapes::Capsule => {
+                //furthest end of the segment pushed out by the radius
+                let (top, bottom) = (self.transformed_points[0], self.transformed_points[1]);
+                let end = if top.dot(d) > bottom.dot(d) { top } else { bottom };
+
+                return end + (d.normalize_or_zero() * self.local_points[2][0] * self.scale);
+            },
+            Shapes::Cuboid => {
+                //the corner is whichever side of each axis faces along d
+                let centre = self.transformed_points[0];
+                let mut corner = centre;
+                for axis in &self.transformed_points[1..4] {
+                    let half = *axis - centre;
+                    corner += if half.dot(d) >= 0.0 { half } else { -half };
+                }
+
+                return corner;
+            },
+            Shapes::Cylinder => {
+                let centre = self.transformed_points[0];
+                let half_height = self.transformed_points[1] - centre;
+                let cap = if half_height.dot(d) >= 0.0 { half_height } else { -half_height };
+
+                return centre + cap + self.rim_support(d);
+            },
+            Shapes::Cone => {
+                let centre = self.transformed_points[0];
+                let half_height = self.transformed_points[1] - centre;
+                let tip = centre + half_height;
+                let base = centre - half_height + self.rim_support(d);
+
+                return if tip.dot(d) > base.dot(d) { tip } else { base };
+            },
+            Shapes::Compound(ref parts) => {
+                return compound_support(parts, d);
+            },
+            Shapes::HalfSpace => {
+                //there's no furthest point on an infinite shape, every query checks for half-spaces before it gets here
+                return self.transformed_points[0];
+            },
+            Shapes::Heightfield { .. } => {
+                return heightfield_support(self, d);
+            },
         }
     }
+
+    //point on the circular rim of a cylinder or cone (relative to the centre of the circle) that's furthest along d
+    //the two radius axes can be any length after scaling so this works for ellipses too
+    fn rim_support(&self, d: Vec3) -> Vec3 {
+        let centre = self.transformed_points[0];
+        let (u, v) = (self.transformed_points[2] - centre, self.transformed_points[3] - centre);
+        let (du, dv) = (d.dot(u), d.dot(v));
+        let length = (du * du + dv * dv).sqrt();
+
+        //d is along the axis so every point on the rim is equally far
+        if length < f32::EPSILON {
+            return Vec3::ZERO;
+        }
+        return (u * du + v * dv) / length;
+    }
     //these constructor functions will ensure that different shape types will be made in specific ways
     pub fn sphere_from_radius(radius: f32) -> Self {
         return Self {
             shape: Shapes::Sphere,
             local_points: vec![Vec3::new(radius, 0.0, 0.0)],
             transformed_points: vec![Vec3::new(radius, 0.0, 0.0)],
+            scale: 1.0,
         }
     }
     pub fn poly_from_points(points: Vec<Vec3>) -> Self {
@@ -63,22 +301,105 @@ impl Collider {
             shape: Shapes::Polyhedron,
             local_points: points.clone(),
             transformed_points: points.clone(),
+            scale: 1.0,
+        }
+    }
+    //same arguments as bevy's shape::Capsule, depth is the length of the middle section so the capsule is depth + 2 * radius tall
+    pub fn capsule_from_radius_depth(radius: f32, depth: f32) -> Self {
+        let points = vec![
+            Vec3::new(0.0, depth / 2.0, 0.0),
+            Vec3::new(0.0, -depth / 2.0, 0.0),
+            Vec3::new(radius, 0.0, 0.0),
+        ];
+        return Self {
+            shape: Shapes::Capsule,
+            local_points: points.clone(),
+            transformed_points: points,
+            scale: 1.0,
+        }
+    }
+    pub fn cuboid_from_half_extents(half_extents: Vec3) -> Self {
+        let points = vec![
+            Vec3::ZERO,
+            Vec3::new(half_extents.x, 0.0, 0.0),
+            Vec3::new(0.0, half_extents.y, 0.0),
+            Vec3::new(0.0, 0.0, half_extents.z),
+        ];
+        return Self {
+            shape: Shapes::Cuboid,
+            local_points: points.clone(),
+            transformed_points: points,
+            scale: 1.0,
+        }
+    }
+    //cylinder and cone both stand up along the y axis and are centred halfway up
+    pub fn cylinder_from_radius_height(radius: f32, height: f32) -> Self {
+        return Self {
+            shape: Shapes::Cylinder,
+            ..Self::round_from_radius_height(radius, height)
+        }
+    }
+    pub fn cone_from_radius_height(radius: f32, height: f32) -> Self {
+        return Self {
+            shape: Shapes::Cone,
+            ..Self::round_from_radius_height(radius, height)
+        }
+    }
+    fn round_from_radius_height(radius: f32, height: f32) -> Self {
+        let points = vec![
+            Vec3::ZERO,
+            Vec3::new(0.0, height / 2.0, 0.0),
+            Vec3::new(radius, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, radius),
+        ];
+        return Self {
+            shape: Shapes::Cylinder,
+            local_points: points.clone(),
+            transformed_points: points,
+            scale: 1.0,
         }
     }
 }
 
 //transform should be applied before the support function is called because currently the transformed_points will be wrong for the first frame
+//GlobalTransform is used so colliders on child entities end up in world space, it only gets filled in by bevy's transform propagation so new colliders sit at the origin until then
+//entities without a parent use their Transform instead since it's the same thing and already includes anything that moved them earlier this frame
+//static colliders are skipped unless they were just added or one of their transforms changed
 pub fn apply_transform_collider (
-    mut colliders_and_transforms: Query<(&mut Collider, &Transform)>,
+    mut colliders_and_transforms: Query<(&mut Collider, Ref<GlobalTransform>, Option<Ref<Transform>>, Option<&Parent>, Option<&BodyType>)>,
 ) {
-    for (mut col, trans,) in colliders_and_transforms.iter_mut() {
-        match col.shape {
+    for (mut col, global, local, parent, body) in colliders_and_transforms.iter_mut() {
+        let moved = global.is_changed() || local.as_ref().map_or(false, |local| local.is_changed());
+        if body.copied().unwrap_or_default() == BodyType::Static && !moved && !col.is_added() {
+            continue;
+        }
+        match (local, parent) {
+            (Some(local), None) => col.set_transform(&GlobalTransform::from(*local)),
+            _ => col.set_transform(&global),
+        }
+    }
+}
+
+impl Collider {
+    fn set_transform(&mut self, trans: &GlobalTransform) {
+        let (scale, _, translation) = trans.to_scale_rotation_translation();
+        //a radius can't be squashed so rounded shapes take the biggest axis of the scale
+        self.scale = scale.abs().max_element();
+        match self.shape {
             Shapes::Sphere => {
-                col.transformed_points[0] = col.local_points[0] + trans.translation;
+                //rotating a sphere does nothing
+                self.transformed_points[0] = self.local_points[0] + translation;
             },
-            Shapes::Polyhedron => {
-                for i in 0..col.local_points.len() {
-                    col.transformed_points[i] = col.local_points[i] + trans.translation;
+            Shapes::Compound(ref mut parts) => {
+                //each part sits at its own offset inside the entity's transform
+                for part in parts.iter_mut() {
+                    part.collider.set_transform(&(*trans * part.transform));
+                }
+            },
+            _ => {
+                //the affine transform handles rotation and non-uniform scale on every point
+                for i in 0..self.local_points.len() {
+                    self.transformed_points[i] = trans.transform_point(self.local_points[i]);
                 }
             }
         }
@@ -86,58 +407,170 @@ pub fn apply_transform_collider (
 }
 
 pub fn collision_update (
-    col: Query<&Collider>
+    col: Query<(Entity, &Collider, Option<&CollisionLayers>)>,
+    mut broad_phase: ResMut<BroadPhaseBackend>,
+    mut stats: ResMut<BroadPhaseStats>,
+    mut contacts: ResMut<Contacts>,
+    mut e_started: EventWriter<CollisionStarted>,
+    mut e_continued: EventWriter<CollisionContinued>,
+    mut e_ended: EventWriter<CollisionEnded>,
+    mut q_ccd: Query<(Entity, &mut Ccd, &Collider)>,
+    q_bodies: Query<&BodyType>,
+    q_rigid_bodies: Query<&RigidBody>,
+    mut diagnostics: ResMut<CollisionDiagnostics>,
 ) {
-    //checks every collider against every other collider
-    for [s1, s2] in col.iter_combinations() {
-        let result = gjk(s1, s2);
-        println!("collision?: {}", result);
+    //the broad phase throws away every pair whose bounding boxes don't overlap
+    let start = Instant::now();
+    let aabbs: Vec<(Entity, ColliderAabb)> = col.iter().map(|(entity, collider, _)| (entity, collider.aabb())).collect();
+    let mut pairs = broad_phase.0.find_pairs(&aabbs);
+    //two colliders that are static or asleep can't have moved into each other
+    let is_static = |entity: Entity| q_bodies.get(entity).copied().unwrap_or_default() == BodyType::Static;
+    let is_resting = |entity: Entity| is_static(entity) || q_rigid_bodies.get(entity).map_or(false, |body| body.sleeping);
+    pairs.retain(|&(e1, e2)| !(is_resting(e1) && is_resting(e2)));
+    stats.record(aabbs.len(), pairs.len());
+    diagnostics.colliders = aabbs.len();
+    diagnostics.candidate_pairs += pairs.len();
+    diagnostics.broad_phase_time += start.elapsed();
+
+    //so sleeping pairs are still touching however they were when they fell asleep
+    let start = Instant::now();
+    let mut touching = HashMap::default();
+    let mut manifolds = HashMap::default();
+    for (&(e1, e2), &contact) in contacts.pairs.iter() {
+        if is_resting(e1) && is_resting(e2) && col.contains(e1) && col.contains(e2) {
+            touching.insert((e1, e2), contact);
+            manifolds.insert((e1, e2), contacts.manifolds[&(e1, e2)].clone());
+        }
+    }
+
+    //only the pairs left over get the full gjk test
+    for (e1, e2) in pairs {
+        let (e1, e2) = (e1.min(e2), e1.max(e2));
+        let Ok([(_, s1, l1), (_, s2, l2)]) = col.get_many([e1, e2]) else {
+            continue;
+        };
+        //layers are checked before gjk since it's far cheaper
+        let (l1, l2) = (l1.copied().unwrap_or_default(), l2.copied().unwrap_or_default());
+        if !l1.interacts_with(&l2) {
+            continue;
+        }
+        diagnostics.narrow_phase_tests += 1;
+        if let Some((contact, manifold)) = collide_manifold_profiled(s1, s2, &mut diagnostics) {
+            touching.insert((e1, e2), contact);
+            manifolds.insert((e1, e2), manifold);
+        }
     }
+    diagnostics.narrow_phase_time += start.elapsed();
+
+    //fast colliders also get swept from where they were last step so they can't skip over anything
+    let start = Instant::now();
+    for (entity, motion) in ccd_motions(&mut q_ccd) {
+        ccd_contacts(entity, motion, &col, &mut touching);
+    }
+    diagnostics.ccd_time += start.elapsed();
+
+    //compare against last frame to work out which pairs are new and which have stopped touching
+    let start = Instant::now();
+    for (&(e1, e2), &contact) in touching.iter() {
+        if contacts.pairs.contains_key(&(e1, e2)) {
+            e_continued.send(CollisionContinued { e1, e2, contact });
+        } else {
+            e_started.send(CollisionStarted { e1, e2, contact });
+        }
+    }
+    for &(e1, e2) in contacts.pairs.keys() {
+        if !touching.contains_key(&(e1, e2)) {
+            e_ended.send(CollisionEnded { e1, e2 });
+        }
+    }
+
+    //ccd hits only have the one point, then anything that was touching last frame gets its impulses back
+    for (key, contact) in touching.iter() {
+        let manifold = manifolds.entry(*key).or_insert_with(|| ContactManifold::from(*contact));
+        if let Some(previous) = contacts.manifolds.get(key) {
+            manifold.warm_start(previous);
+        }
+    }
+
+    contacts.pairs = touching;
+    contacts.manifolds = manifolds;
+    diagnostics.contact_time += start.elapsed();
 }
 
+/**
+ * What gjk decided about a pair of colliders.
+ * Degenerate means it couldn't make up its mind, which happens when the shapes are only just touching
+ * or the support points stop getting anywhere (flat or repeated points). Treat it as not colliding.
+ */
+#[derive(Clone, Debug)]
+pub enum GjkResult {
+    //the final tetrahedron, it surrounds the origin so it can be used as the starting polytope for epa
+    Intersecting(Vec<SupportPoint>),
+    Separated,
+    Degenerate,
+}
 
+impl GjkResult {
+    pub fn is_intersecting(&self) -> bool {
+        return matches!(self, GjkResult::Intersecting(_));
+    }
+}
 
 /**
- * Returns true if objects have collided otherwise false.
+ * Works out whether two colliders overlap.
+ * It gives up after GJK_MAX_ITERATIONS or as soon as a new support point gets less than GJK_TOLERANCE closer to the origin,
+ * so it always finishes even on shapes it can't make sense of.
  */
 pub fn gjk (
     s1: &Collider,
     s2: &Collider,
-) -> bool {
+) -> GjkResult {
+    return gjk_iterations(s1, s2).0;
+}
+
+//gjk that also says how many iterations it took, for CollisionDiagnostics
+fn gjk_iterations (
+    s1: &Collider,
+    s2: &Collider,
+) -> (GjkResult, u32) {
     let mut d = Vec3::ONE.normalize();
     let mut simplex = vec![support(s1, s2, &d)];
-    d = Vec3::ZERO - simplex[0];
-        loop {
-            d = d.normalize();
-            println!("selecting new point");
-            let p = support(s1, s2, &d);
-            if p.dot(d) <= 0.0 {
-                println!("new point not past origin");
-                return false;
-            }
+    d = Vec3::ZERO - simplex[0].point;
+    for iteration in 1..=GJK_MAX_ITERATIONS {
+        //a zero direction means the origin is right on the simplex so the shapes are touching
+        if d.length_squared() < GJK_TOLERANCE * GJK_TOLERANCE || !d.is_finite() {
+            return (GjkResult::Degenerate, iteration);
+        }
+        d = d.normalize();
+        let p = support(s1, s2, &d);
+        if p.point.dot(d) <= 0.0 {
+            return (GjkResult::Separated, iteration);
+        }
 
-            println!("new point is past origin");
-            simplex.push(p);
-            if handle_simplex(&mut simplex, &mut d) {
-                return true;
-            }
-            println!("the origin is not yet surrounded");
+        //the new point has to get further towards the origin than the simplex already is, otherwise it will loop forever
+        let furthest = simplex.iter().map(|point| point.point.dot(d)).fold(f32::MIN, f32::max);
+        if p.point.dot(d) - furthest < GJK_TOLERANCE {
+            return (GjkResult::Degenerate, iteration);
         }
+
+        simplex.push(p);
+        if handle_simplex(&mut simplex, &mut d) {
+            return (GjkResult::Intersecting(simplex), iteration);
+        }
+    }
+    return (GjkResult::Degenerate, GJK_MAX_ITERATIONS);
 }
 
 fn handle_simplex(
-    mut simplex: &mut Vec<Vec3>,
+    simplex: &mut Vec<SupportPoint>,
     d: &mut Vec3,
 ) -> bool {
     if simplex.len() == 2 {
-        println!("line case");
         return line_case(simplex, d);
     } else if simplex.len() == 3 {
-        println!("triangle case");
         return triangle_case(simplex, d);
     }
-    println!("tetrahedron case");
-    return tetrahedron_case(&mut simplex, d); 
+    return tetrahedron_case(simplex, d);
 }
 
 /*
@@ -148,21 +581,25 @@ two points => vector
 e.g. ao is the vector from a to the origin, ab is a to b, etc...
  */
 fn line_case(
-    simplex: &Vec<Vec3>,
+    simplex: &Vec<SupportPoint>,
     direction: &mut Vec3,
 ) -> bool {
-    let (pb, pa) = (simplex[0], simplex[1]);
+    let (pb, pa) = (simplex[0].point, simplex[1].point);
     let (ab, ao) = (pb - pa, -pa);
     *direction = ab.cross(ao.cross(ab));
+    //the origin is on the line itself so any direction off the line will do
+    if direction.length_squared() < GJK_TOLERANCE * GJK_TOLERANCE {
+        *direction = ab.any_orthogonal_vector();
+    }
     return false;
 }
 
 fn triangle_case(
-    simplex: &mut Vec<Vec3>,
+    simplex: &mut Vec<SupportPoint>,
     direction: &mut Vec3,
 ) -> bool {
     //i think i can get rid of the variable ao
-    let (pc, pb, pa) = (simplex[0], simplex[1], simplex[2]);
+    let (pc, pb, pa) = (simplex[0].point, simplex[1].point, simplex[2].point);
     let (ac, ab, ao) = (pc-pa, pb-pa, -pa);
     let abc = ab.cross(ac);
     
@@ -194,16 +631,17 @@ fn triangle_case(
 }
 
 fn tetrahedron_case(
-    simplex: &mut Vec<Vec3>,
+    simplex: &mut Vec<SupportPoint>,
     direction: &mut Vec3,
 ) -> bool {
-    let (pd, pc, pb, pa) = (simplex[0], simplex[1], simplex[2], simplex[3]);
+    let (pd, pc, pb, pa) = (simplex[0].point, simplex[1].point, simplex[2].point, simplex[3].point);
     //region abc
     let (ab, ac, ad, ao) = (pb - pa, pc - pa, pd - pa, -pa);
 
     let (abc, acd, adb) = (ab.cross(ac), ac.cross(ad), ad.cross(ab));
 
-    if abc.dot(ac) > 0.0 {
+    //each face has to be checked against the origin, not against one of its own edges
+    if abc.dot(ao) > 0.0 {
         simplex.remove(0);
         return triangle_case(simplex, direction)
     }
@@ -213,7 +651,7 @@ fn tetrahedron_case(
         return triangle_case(simplex, direction)
     }
 
-    if adb.dot(ac) > 0.0 {
+    if adb.dot(ao) > 0.0 {
         simplex.remove(1);
         return triangle_case(simplex, direction)
     }
@@ -221,12 +659,26 @@ fn tetrahedron_case(
     return true;
 }
 
+//a point on the minkowski difference along with the points on each collider that made it
+//keeping these around lets epa work out where the contact is on each object
+#[derive(Clone, Copy, Debug)]
+pub struct SupportPoint {
+    pub point: Vec3,
+    pub on_s1: Vec3,
+    pub on_s2: Vec3,
+}
+
 fn support (
     s1: &Collider,
     s2: &Collider,
     d: &Vec3,
-) -> Vec3 {
-    return s1.support(*d) - s2.support(-(*d));
+) -> SupportPoint {
+    let (on_s1, on_s2) = (s1.support(*d), s2.support(-(*d)));
+    return SupportPoint {
+        point: on_s1 - on_s2,
+        on_s1,
+        on_s2,
+    };
 }
 
 pub fn col_test_case (
@@ -246,26 +698,74 @@ pub fn col_test_case (
     //test cube at (0,0,0), length of all sides are 2
     commands.spawn( (
         Collider::poly_from_points(points),
-        Transform::from_translation(Vec3::new(2.0, 2.0, 2.0)),
+        TransformBundle::from_transform(Transform::from_translation(Vec3::new(2.0, 2.0, 2.0))),
     ));
 
     //test sphere at (0,0,0), radius of 1
     commands.spawn((
         Collider::sphere_from_radius(2.0),
-        Transform::from_translation(Vec3::ZERO),
+        TransformBundle::from_transform(Transform::from_translation(Vec3::ZERO)),
     ));
 }
 
+/**
+ * The stages of a physics tick, they run one after another in FixedUpdate.
+ * Game code that moves things around should go in Move so the colliders are up to date before collisions are checked,
+ * and anything reacting to collision events should run after Collide in the same tick.
+ */
+#[derive(SystemSet, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum PhysicsSet {
+    Move,
+    Collide,
+    Solve,
+}
+
+/**
+ * Collision detection and rigid body physics, all run in FixedUpdate so results don't depend on the frame rate.
+ * The tick rate is the period of bevy's FixedTime resource, insert your own to change it.
+ * Collision events are sent from FixedUpdate, Update systems reading them can miss some when a frame has no ticks.
+ * What the pipeline did each frame goes in CollisionDiagnostics, add LogDiagnosticsPlugin to have it printed.
+ */
 pub struct CollisionPlugin;
 
 impl Plugin for CollisionPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, (
-            collision_update,
-            apply_transform_collider
-                .before(collision_update),
-        ));
+        app.init_resource::<BroadPhaseBackend>()
+            .init_resource::<BroadPhaseStats>()
+            .init_resource::<Contacts>()
+            .init_resource::<Gravity>()
+            .init_resource::<CollisionDiagnostics>()
+            .init_resource::<FixedTime>()
+            .add_event::<CollisionStarted>()
+            .add_event::<CollisionContinued>()
+            .add_event::<CollisionEnded>()
+            .configure_sets(FixedUpdate, (PhysicsSet::Move, PhysicsSet::Collide, PhysicsSet::Solve).chain())
+            .add_systems(Update, (
+                collider_from_mesh,
+                auto_collider,
+            ))
+            .add_systems(FixedUpdate, (
+                record_previous_transforms
+                    .before(PhysicsSet::Move),
+                move_characters
+                    .in_set(PhysicsSet::Move),
+                //several ticks can run in one frame so global transforms have to be brought up to date in between
+                (start_stage_timer, sync_simple_transforms, propagate_transforms, apply_transform_collider, finish_transform_timer, collision_update)
+                    .chain()
+                    .in_set(PhysicsSet::Collide),
+                (start_stage_timer, update_sleep, apply_gravity, resolve_contacts, solve_contacts, integrate_bodies, finish_solve_timer)
+                    .chain()
+                    .in_set(PhysicsSet::Solve),
+                record_current_transforms
+                    .after(PhysicsSet::Solve),
+            ))
+            .add_systems(PreUpdate, (restore_physics_transforms, reset_collision_diagnostics))
+            .add_systems(PostUpdate, (
+                interpolate_transforms.before(TransformSystem::TransformPropagate),
+                measure_collision_diagnostics,
+            ));
             //.add_systems(Startup, col_test_case);
+        register_collision_diagnostics(app);
     }
 }
 
@@ -274,17 +774,7 @@ mod tests {
     use bevy::render::render_resource::encase::rts_array::Length;
 
     use super::*;
-
-    const POINTS: &[Vec3] = &[
-            Vec3::new(1.0,1.0,1.0),
-            Vec3::new(1.0,1.0,-1.0),
-            Vec3::new(1.0,-1.0,1.0),
-            Vec3::new(1.0,-1.0,-1.0),
-            Vec3::new(-1.0,1.0,1.0),
-            Vec3::new(-1.0,1.0,-1.0),
-            Vec3::new(-1.0,-1.0,1.0),
-            Vec3::new(-1.0,-1.0,-1.0),
-        ];
+    use super::fixtures::CUBE_POINTS;
 
     fn tranform_helper_function(points: &Vec<Vec3>, translation: Vec3) -> Vec<Vec3> {
         let mut translated_points: Vec<Vec3> = Vec::new();
@@ -296,7 +786,7 @@ mod tests {
 
     #[test]
     fn support_test_when_local() { //tests if the support function returns the correct point
-        let points = POINTS.to_vec();
+        let points = CUBE_POINTS.to_vec();
         let cube = Collider::poly_from_points(points);
         let sphere = Collider::sphere_from_radius(3.0);
 
@@ -320,7 +810,7 @@ mod tests {
 
     #[test]
     fn support_test_when_translated() {
-        let points = POINTS.to_vec();
+        let points = CUBE_POINTS.to_vec();
 
         let translated_points: Vec<Vec3> = tranform_helper_function(&points, Vec3::new(100.0, 234.5, -63.0));
 
@@ -328,11 +818,13 @@ mod tests {
             shape: Shapes::Polyhedron,
             local_points: points,
             transformed_points: translated_points,
+            scale: 1.0,
         };
         let sphere = Collider {
             shape: Shapes::Sphere,
             local_points: vec![Vec3::new(3.0, 0.0, 0.0)],
             transformed_points: vec![Vec3::new(212.0, -12.2, 17.0)],
+            scale: 1.0,
         };
 
         //for reference the cube's origin is (100, 234.5, -63) and the sphere's origin is (209, -12.2, 17)
@@ -353,7 +845,7 @@ mod tests {
 
     #[test]
     fn cube_intersect_cube() {
-        let points = POINTS.to_vec();
+        let points = CUBE_POINTS.to_vec();
 
         let translated_points = tranform_helper_function(&points, Vec3::new(1.5, 1.5, 1.5));
         let extra_points = tranform_helper_function(&points, Vec3::new(0.0, 0.0, 0.0));
@@ -362,25 +854,191 @@ mod tests {
             shape: Shapes::Polyhedron,
             local_points: points,
             transformed_points: translated_points,
+            scale: 1.0,
         };
 
         let cube2 = Collider::poly_from_points(extra_points);
 
-        assert!(gjk(&cube1, &cube2));
+        assert!(gjk(&cube1, &cube2).is_intersecting());
     }
 
     #[test]
     fn cube_intersect_sphere() {
-        let points = POINTS.to_vec();
+        let points = CUBE_POINTS.to_vec();
         let translated_points = tranform_helper_function(&points, Vec3::new(0.0, 2.5, 0.0));
         let cube = Collider {
             shape: Shapes::Sphere,
             local_points: points,
             transformed_points: translated_points,
+            scale: 1.0,
         };
         let sphere = Collider::sphere_from_radius(2.0);
 
-        assert!(gjk(&cube, &sphere));
+        assert!(gjk(&cube, &sphere).is_intersecting());
+    }
+
+    #[test]
+    fn transform_applies_rotation_and_scale() {
+        let mut world = World::new();
+        let cube = world.spawn((
+            Collider::poly_from_points(CUBE_POINTS.to_vec()),
+            GlobalTransform::from(Transform {
+                translation: Vec3::new(10.0, 0.0, 0.0),
+                rotation: Quat::from_rotation_y(std::f32::consts::FRAC_PI_4),
+                scale: Vec3::new(2.0, 1.0, 2.0),
+            }),
+        )).id();
+        let sphere = world.spawn((
+            Collider::sphere_from_radius(1.0),
+            GlobalTransform::from(Transform::from_xyz(0.0, 5.0, 0.0).with_scale(Vec3::splat(3.0))),
+        )).id();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(apply_transform_collider);
+        schedule.run(&mut world);
+
+        //the cube is 4 wide in x and z and turned 45 degrees so its corner sits 2 * sqrt(2) along x
+        let cube = world.get::<Collider>(cube).unwrap();
+        let d = Vec3::X;
+        let expected = 10.0 + 2.0 * std::f32::consts::SQRT_2;
+        assert!((cube.support(d).x - expected).abs() < 0.001, "support returned {}", cube.support(d));
+
+        //the sphere's radius of 1 should now be 3
+        let sphere = world.get::<Collider>(sphere).unwrap();
+        let d = Vec3::Y;
+        assert!(sphere.support(d).abs_diff_eq(Vec3::new(0.0, 8.0, 0.0), 0.001), "support returned {}", sphere.support(d));
+    }
+
+    #[test]
+    fn support_analytic_shapes() {
+        let capsule = Collider::capsule_from_radius_depth(1.0, 2.0);
+        let d = Vec3::Y;
+        assert_eq!(capsule.support(d), Vec3::new(0.0, 2.0, 0.0), "support returned {}", capsule.support(d));
+        let d = Vec3::new(1.0, -1.0, 0.0).normalize();
+        assert!(capsule.support(d).abs_diff_eq(Vec3::new(0.0, -1.0, 0.0) + d, 0.001), "support returned {}", capsule.support(d));
+
+        let cuboid = Collider::cuboid_from_half_extents(Vec3::new(1.0, 2.0, 3.0));
+        let d = Vec3::new(-1.0, 1.0, -1.0).normalize();
+        assert_eq!(cuboid.support(d), Vec3::new(-1.0, 2.0, -3.0), "support returned {}", cuboid.support(d));
+
+        //straight out the side and diagonally up off the rim
+        let cylinder = Collider::cylinder_from_radius_height(2.0, 4.0);
+        let d = Vec3::X;
+        assert_eq!(cylinder.support(d).x, 2.0, "support returned {}", cylinder.support(d));
+        let d = Vec3::new(0.0, 1.0, -1.0).normalize();
+        assert!(cylinder.support(d).abs_diff_eq(Vec3::new(0.0, 2.0, -2.0), 0.001), "support returned {}", cylinder.support(d));
+
+        //a steep direction hits the tip and a shallow one hits the base
+        let cone = Collider::cone_from_radius_height(1.0, 4.0);
+        let d = Vec3::new(1.0, 1.0, 0.0).normalize();
+        assert_eq!(cone.support(d), Vec3::new(0.0, 2.0, 0.0), "support returned {}", cone.support(d));
+        let d = Vec3::new(1.0, -0.1, 0.0).normalize();
+        assert!(cone.support(d).abs_diff_eq(Vec3::new(1.0, -2.0, 0.0), 0.001), "support returned {}", cone.support(d));
+    }
+
+    #[test]
+    fn capsule_intersect_cuboid() {
+        let mut world = World::new();
+        let capsule = world.spawn((
+            Collider::capsule_from_radius_depth(1.0, 1.0),
+            GlobalTransform::from_translation(Vec3::new(0.0, 0.5, 0.0)),
+        )).id();
+        let wall = world.spawn((
+            Collider::cuboid_from_half_extents(Vec3::splat(5.0)),
+            GlobalTransform::from_translation(Vec3::new(5.9, 0.0, 0.0)),
+        )).id();
+        let far_wall = world.spawn((
+            Collider::cuboid_from_half_extents(Vec3::splat(5.0)),
+            GlobalTransform::from_translation(Vec3::new(6.1, 0.0, 0.0)),
+        )).id();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(apply_transform_collider);
+        schedule.run(&mut world);
+
+        let capsule = world.get::<Collider>(capsule).unwrap();
+        assert!(gjk(capsule, world.get::<Collider>(wall).unwrap()).is_intersecting());
+        assert!(!gjk(capsule, world.get::<Collider>(far_wall).unwrap()).is_intersecting());
+    }
+
+    #[test]
+    fn layers_filter_pairs() {
+        let (player, spell, wall) = (1 << 0, 1 << 1, 1 << 2);
+        let player_layers = CollisionLayers::new(player, CollisionLayers::ALL);
+        let spell_layers = CollisionLayers::new(spell, CollisionLayers::ALL & !player & !spell);
+        let wall_layers = CollisionLayers::new(wall, CollisionLayers::ALL & !wall);
+
+        assert!(!spell_layers.interacts_with(&player_layers));
+        assert!(!spell_layers.interacts_with(&spell_layers));
+        assert!(!wall_layers.interacts_with(&wall_layers));
+        assert!(spell_layers.interacts_with(&wall_layers));
+        assert!(player_layers.interacts_with(&wall_layers));
+        assert!(CollisionLayers::default().interacts_with(&player_layers));
+    }
+
+    #[test]
+    fn collision_events_start_continue_and_end() {
+        let mut app = App::new();
+        app.add_plugins(CollisionPlugin);
+        let e1 = app.world.spawn((
+            Collider::sphere_from_radius(1.0),
+            GlobalTransform::from_translation(Vec3::ZERO),
+        )).id();
+        let e2 = app.world.spawn((
+            Collider::sphere_from_radius(1.0),
+            GlobalTransform::from_translation(Vec3::new(1.5, 0.0, 0.0)),
+            BodyType::Kinematic,
+        )).id();
+
+        app.world.run_schedule(FixedUpdate);
+        let started = app.world.resource::<Events<CollisionStarted>>();
+        let mut reader = started.get_reader();
+        let event = reader.iter(started).next().expect("no CollisionStarted event");
+        assert_eq!((event.e1, event.e2), (e1.min(e2), e1.max(e2)));
+        assert!(app.world.resource::<Contacts>().contains(e2, e1));
+
+        app.world.run_schedule(FixedUpdate);
+        let continued = app.world.resource::<Events<CollisionContinued>>();
+        assert_eq!(continued.get_reader().iter(continued).count(), 1);
+
+        *app.world.get_mut::<GlobalTransform>(e2).unwrap() = GlobalTransform::from_translation(Vec3::new(5.0, 0.0, 0.0));
+        app.world.run_schedule(FixedUpdate);
+        let ended = app.world.resource::<Events<CollisionEnded>>();
+        assert_eq!(ended.get_reader().iter(ended).count(), 1);
+        assert!(!app.world.resource::<Contacts>().contains(e1, e2));
+    }
+
+    #[test]
+    fn static_pairs_are_skipped() {
+        let mut app = App::new();
+        app.add_plugins(CollisionPlugin);
+        let e1 = app.world.spawn((Collider::sphere_from_radius(1.0), GlobalTransform::IDENTITY)).id();
+        let e2 = app.world.spawn((Collider::sphere_from_radius(1.0), GlobalTransform::IDENTITY)).id();
+
+        app.world.run_schedule(FixedUpdate);
+        assert!(!app.world.resource::<Contacts>().contains(e1, e2));
+
+        app.world.entity_mut(e2).insert(BodyType::Dynamic);
+        app.world.run_schedule(FixedUpdate);
+        assert!(app.world.resource::<Contacts>().contains(e1, e2));
+    }
+
+    #[test]
+    fn static_colliders_only_update_when_moved() {
+        let mut world = World::new();
+        let wall = world.spawn((Collider::sphere_from_radius(1.0), GlobalTransform::IDENTITY)).id();
+        let mut schedule = Schedule::default();
+        schedule.add_systems(apply_transform_collider);
+        schedule.run(&mut world);
+
+        //nothing moved so the collider shouldn't have been touched
+        world.clear_trackers();
+        schedule.run(&mut world);
+        assert!(!world.entity(wall).get_ref::<Collider>().unwrap().is_changed());
+
+        *world.get_mut::<GlobalTransform>(wall).unwrap() = GlobalTransform::from_translation(Vec3::X);
+        schedule.run(&mut world);
+        assert_eq!(world.get::<Collider>(wall).unwrap().support(Vec3::X), Vec3::new(2.0, 0.0, 0.0));
     }
 
     #[test]
@@ -392,4 +1050,21 @@ mod tests {
     fn close_but_no_intersection() {
         
     }
+
+    #[test]
+    fn gjk_finishes_on_awkward_shapes() {
+        //two flat squares in the same plane, every support point is on the plane so gjk can never build a tetrahedron
+        let square = vec![Vec3::new(1.0, 0.0, 1.0), Vec3::new(1.0, 0.0, -1.0), Vec3::new(-1.0, 0.0, 1.0), Vec3::new(-1.0, 0.0, -1.0)];
+        let s1 = Collider::poly_from_points(square.clone());
+        let s2 = Collider::poly_from_points(tranform_helper_function(&square, Vec3::new(0.5, 0.0, 0.5)));
+        assert!(!gjk(&s1, &s2).is_intersecting());
+
+        //cubes sharing a face exactly could go either way, it just has to finish
+        let c1 = Collider::poly_from_points(CUBE_POINTS.to_vec());
+        let c2 = Collider::poly_from_points(tranform_helper_function(&CUBE_POINTS.to_vec(), Vec3::new(2.0, 0.0, 0.0)));
+        gjk(&c1, &c2);
+
+        let c3 = Collider::poly_from_points(tranform_helper_function(&CUBE_POINTS.to_vec(), Vec3::new(10.0, 0.0, 0.0)));
+        assert!(matches!(gjk(&c1, &c3), GjkResult::Separated));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,282 @@
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+
+use super::{half_space_aabb, Collider, Shapes};
+
+//the ground plane is 200 units across so this splits it into a 20 x 20 grid
+pub const SPATIAL_HASH_CELL_SIZE: f32 = 10.0;
+//boxes spanning more cells than this along any axis are checked against everything instead of being put in the grid
+pub const SPATIAL_HASH_MAX_CELLS: f32 = 64.0;
+
+//axis aligned bounding box in world space, built from the collider's support function
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColliderAabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl ColliderAabb {
+    //covers all of space, half-spaces that aren't lined up with an axis get this
+    pub const INFINITE: ColliderAabb = ColliderAabb { min: Vec3::NEG_INFINITY, max: Vec3::INFINITY };
+
+    pub fn intersects(&self, other: &ColliderAabb) -> bool {
+        return self.min.cmple(other.max).all() && other.min.cmple(self.max).all();
+    }
+}
+
+impl Collider {
+    //the furthest point along each axis gives the box for any shape
+    pub fn aabb(&self) -> ColliderAabb {
+        if let Shapes::HalfSpace = self.shape {
+            return half_space_aabb(self);
+        }
+        return ColliderAabb {
+            min: Vec3::new(
+                self.support(Vec3::NEG_X).x,
+                self.support(Vec3::NEG_Y).y,
+                self.support(Vec3::NEG_Z).z,
+            ),
+            max: Vec3::new(
+                self.support(Vec3::X).x,
+                self.support(Vec3::Y).y,
+                self.support(Vec3::Z).z,
+            ),
+        };
+    }
+}
+
+/**
+ * A broad phase takes the bounding box of every collider and returns the pairs that might be touching.
+ * Only those pairs get passed on to gjk.
+ */
+pub trait BroadPhase: Send + Sync + 'static {
+    fn find_pairs(&mut self, aabbs: &[(Entity, ColliderAabb)]) -> Vec<(Entity, Entity)>;
+}
+
+//the broad phase collision_update uses, swap it out with app.insert_resource
+#[derive(Resource)]
+pub struct BroadPhaseBackend(pub Box<dyn BroadPhase>);
+
+impl Default for BroadPhaseBackend {
+    fn default() -> Self {
+        return Self(Box::new(SweepAndPrune::default()));
+    }
+}
+
+//filled in by collision_update every frame
+#[derive(Resource, Default, Debug)]
+pub struct BroadPhaseStats {
+    pub colliders: usize,
+    //how many pairs a brute force check would have tested
+    pub total_pairs: usize,
+    pub candidate_pairs: usize,
+    pub culled_pairs: usize,
+}
+
+impl BroadPhaseStats {
+    pub fn record(&mut self, colliders: usize, candidate_pairs: usize) {
+        self.colliders = colliders;
+        self.total_pairs = colliders * colliders.saturating_sub(1) / 2;
+        self.candidate_pairs = candidate_pairs;
+        self.culled_pairs = self.total_pairs - candidate_pairs;
+    }
+}
+
+/**
+ * Sorts the boxes along the x axis and only compares boxes whose x ranges overlap.
+ * The sorted order is kept between frames so the sort is almost free when things don't move much.
+ */
+#[derive(Default)]
+pub struct SweepAndPrune {
+    order: Vec<usize>,
+}
+
+impl BroadPhase for SweepAndPrune {
+    fn find_pairs(&mut self, aabbs: &[(Entity, ColliderAabb)]) -> Vec<(Entity, Entity)> {
+        if self.order.len() != aabbs.len() {
+            self.order = (0..aabbs.len()).collect();
+        }
+        //insertion sort is close to linear on the mostly sorted order from last frame
+        for i in 1..self.order.len() {
+            let mut j = i;
+            while j > 0 && aabbs[self.order[j - 1]].1.min.x > aabbs[self.order[j]].1.min.x {
+                self.order.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+
+        let mut pairs = Vec::new();
+        let mut active: Vec<usize> = Vec::new();
+        for &i in &self.order {
+            let (entity, aabb) = &aabbs[i];
+            //anything that ends before this box starts can never overlap with the rest
+            active.retain(|&j| aabbs[j].1.max.x >= aabb.min.x);
+            for &j in &active {
+                if aabbs[j].1.intersects(aabb) {
+                    pairs.push((aabbs[j].0, *entity));
+                }
+            }
+            active.push(i);
+        }
+        return pairs;
+    }
+}
+
+/**
+ * Buckets every box into a uniform grid and only compares boxes that share a cell.
+ */
+pub struct SpatialHash {
+    pub cell_size: f32,
+    cells: HashMap<IVec3, Vec<usize>>,
+}
+
+impl SpatialHash {
+    pub fn new(cell_size: f32) -> Self {
+        return Self {
+            cell_size,
+            cells: HashMap::default(),
+        };
+    }
+
+    fn cell(&self, point: Vec3) -> IVec3 {
+        return (point / self.cell_size).floor().as_ivec3();
+    }
+
+    //infinite or huge boxes would need more cells than there are integers to count them with
+    fn fits_grid(&self, aabb: &ColliderAabb) -> bool {
+        let cells = (aabb.max - aabb.min) / self.cell_size;
+        return aabb.min.is_finite() && aabb.max.is_finite() && cells.max_element() <= SPATIAL_HASH_MAX_CELLS;
+    }
+}
+
+impl Default for SpatialHash {
+    fn default() -> Self {
+        return Self::new(SPATIAL_HASH_CELL_SIZE);
+    }
+}
+
+impl BroadPhase for SpatialHash {
+    fn find_pairs(&mut self, aabbs: &[(Entity, ColliderAabb)]) -> Vec<(Entity, Entity)> {
+        //buckets that are still in use get their allocations reused next frame
+        for bucket in self.cells.values_mut() {
+            bucket.clear();
+        }
+
+        let mut pairs = Vec::new();
+        let mut seen: HashSet<(usize, usize)> = HashSet::default();
+        let mut oversized: Vec<usize> = Vec::new();
+        for (i, (_, aabb)) in aabbs.iter().enumerate() {
+            if !self.fits_grid(aabb) {
+                oversized.push(i);
+                continue;
+            }
+            let (min, max) = (self.cell(aabb.min), self.cell(aabb.max));
+            for x in min.x..=max.x {
+                for y in min.y..=max.y {
+                    for z in min.z..=max.z {
+                        let bucket = self.cells.entry(IVec3::new(x, y, z)).or_default();
+                        for &j in bucket.iter() {
+                            //big boxes share lots of cells with each other so only report them once
+                            if aabbs[j].1.intersects(aabb) && seen.insert((j, i)) {
+                                pairs.push((aabbs[j].0, aabbs[i].0));
+                            }
+                        }
+                        bucket.push(i);
+                    }
+                }
+            }
+        }
+        //cells nothing is in any more would otherwise pile up behind everything that moves
+        self.cells.retain(|_, bucket| !bucket.is_empty());
+
+        for (n, &i) in oversized.iter().enumerate() {
+            for (j, (entity, aabb)) in aabbs.iter().enumerate() {
+                //pairs of oversized boxes only get reported once
+                if j == i || oversized[..n].contains(&j) {
+                    continue;
+                }
+                if aabbs[i].1.intersects(aabb) {
+                    pairs.push((aabbs[i].0, *entity));
+                }
+            }
+        }
+        return pairs;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn boxes() -> Vec<(Entity, ColliderAabb)> {
+        return vec![
+            (Entity::from_raw(0), ColliderAabb { min: Vec3::ZERO, max: Vec3::ONE }),
+            (Entity::from_raw(1), ColliderAabb { min: Vec3::splat(0.5), max: Vec3::splat(1.5) }),
+            (Entity::from_raw(2), ColliderAabb { min: Vec3::splat(50.0), max: Vec3::splat(51.0) }),
+            //spans several hash cells and overlaps with both of the first two boxes
+            (Entity::from_raw(3), ColliderAabb { min: Vec3::splat(-25.0), max: Vec3::splat(0.75) }),
+        ];
+    }
+
+    fn sorted(mut pairs: Vec<(Entity, Entity)>) -> Vec<(u32, u32)> {
+        let mut indices: Vec<(u32, u32)> = pairs
+            .drain(..)
+            .map(|(a, b)| (a.index().min(b.index()), a.index().max(b.index())))
+            .collect();
+        indices.sort();
+        return indices;
+    }
+
+    #[test]
+    fn sweep_and_prune_finds_overlaps() {
+        let pairs = SweepAndPrune::default().find_pairs(&boxes());
+        assert_eq!(sorted(pairs), vec![(0, 1), (0, 3), (1, 3)]);
+    }
+
+    #[test]
+    fn spatial_hash_finds_overlaps() {
+        let pairs = SpatialHash::default().find_pairs(&boxes());
+        assert_eq!(sorted(pairs), vec![(0, 1), (0, 3), (1, 3)]);
+    }
+
+    #[test]
+    fn spatial_hash_handles_huge_boxes() {
+        let mut aabbs = boxes();
+        //one that goes on forever and one too big for the grid, both overlap everything except box 2
+        aabbs.push((Entity::from_raw(4), ColliderAabb { min: Vec3::NEG_INFINITY, max: Vec3::splat(1.0) }));
+        aabbs.push((Entity::from_raw(5), ColliderAabb { min: Vec3::splat(-1.0e30), max: Vec3::splat(10.0) }));
+
+        let expected = vec![(0, 1), (0, 3), (0, 4), (0, 5), (1, 3), (1, 4), (1, 5), (3, 4), (3, 5), (4, 5)];
+        assert_eq!(sorted(SpatialHash::default().find_pairs(&aabbs)), expected);
+    }
+
+    #[test]
+    fn spatial_hash_forgets_empty_cells() {
+        let mut hash = SpatialHash::default();
+        //a box that fits in one cell flying across a hundred of them
+        for step in 0..100 {
+            let min = Vec3::new(step as f32 * SPATIAL_HASH_CELL_SIZE + 1.0, 1.0, 1.0);
+            hash.find_pairs(&[(Entity::from_raw(0), ColliderAabb { min, max: min + Vec3::ONE })]);
+            assert_eq!(hash.cells.len(), 1);
+        }
+    }
+
+    #[test]
+    fn unbounded_boxes_pair_with_everything_they_touch() {
+        let mut aabbs = boxes();
+        //a floor at y = 0.25 that goes on forever in x and z
+        aabbs.push((Entity::from_raw(4), ColliderAabb { min: Vec3::NEG_INFINITY, max: Vec3::new(f32::INFINITY, 0.25, f32::INFINITY) }));
+
+        let expected = vec![(0, 1), (0, 3), (0, 4), (1, 3), (3, 4)];
+        assert_eq!(sorted(SpatialHash::default().find_pairs(&aabbs)), expected);
+        assert_eq!(sorted(SweepAndPrune::default().find_pairs(&aabbs)), expected);
+    }
+
+    #[test]
+    fn stats_count_culled_pairs() {
+        let mut stats = BroadPhaseStats::default();
+        stats.record(4, 3);
+        assert_eq!(stats.total_pairs, 6);
+        assert_eq!(stats.culled_pairs, 3);
+    }
+}
@@ -0,0 +1,136 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use super::{cast_shape_from, CastHit, Collider, ColliderAabb, CollisionLayers, Contact};
+
+/**
+ * Opts a collider into continuous collision detection.
+ * Every step it gets swept from where it was last step to where it is now, so fast things like spells can't jump through a wall between frames.
+ * Only the movement is swept, any rotation between steps is ignored.
+ */
+#[derive(Component, Default)]
+pub struct Ccd {
+    previous: Option<Vec3>,
+}
+
+/**
+ * Sweeps s1 backwards along motion to where it started and returns the first point it touched s2 on its way to where it is now.
+ * The hit distance is measured from the start, so distance / motion.length() is the time of impact as a fraction of the step.
+ */
+pub fn sweep (
+    s1: &Collider,
+    s2: &Collider,
+    motion: Vec3,
+) -> Option<CastHit> {
+    let distance = motion.length();
+    if distance < f32::EPSILON {
+        return None;
+    }
+    return cast_shape_from(s1, s2, -motion, motion / distance, distance);
+}
+
+//works out the movement of every ccd collider since the last step and remembers where they are now
+//the centre of the collider's box is used so it lines up with wherever apply_transform_collider put the points
+pub(super) fn ccd_motions(q_ccd: &mut Query<(Entity, &mut Ccd, &Collider)>) -> Vec<(Entity, Vec3)> {
+    let mut motions = Vec::new();
+    for (entity, mut ccd, collider) in q_ccd.iter_mut() {
+        let aabb = collider.aabb();
+        let current = (aabb.min + aabb.max) / 2.0;
+        if let Some(previous) = ccd.previous.replace(current) {
+            motions.push((entity, current - previous));
+        }
+    }
+    return motions;
+}
+
+//adds a contact for anything the collider passed through on its way here that it isn't already touching
+pub(super) fn ccd_contacts (
+    entity: Entity,
+    motion: Vec3,
+    colliders: &Query<(Entity, &Collider, Option<&CollisionLayers>)>,
+    touching: &mut HashMap<(Entity, Entity), Contact>,
+) {
+    if motion.length_squared() < f32::EPSILON {
+        return;
+    }
+    let Ok((_, collider, layers)) = colliders.get(entity) else {
+        return;
+    };
+    let layers = layers.copied().unwrap_or_default();
+
+    //the box covering the whole path is enough to rule out most colliders
+    let end = collider.aabb();
+    let swept = ColliderAabb {
+        min: end.min.min(end.min - motion),
+        max: end.max.max(end.max - motion),
+    };
+
+    for (other, other_collider, other_layers) in colliders.iter() {
+        let key = (entity.min(other), entity.max(other));
+        if other == entity || touching.contains_key(&key) {
+            continue;
+        }
+        if !layers.interacts_with(&other_layers.copied().unwrap_or_default()) || !swept.intersects(&other_collider.aabb()) {
+            continue;
+        }
+        let Some(hit) = sweep(collider, other_collider, motion) else {
+            continue;
+        };
+
+        //they only touched in passing so there's no depth, the normal points from the ccd collider into what it hit
+        let contact = Contact {
+            depth: 0.0,
+            normal: -hit.normal,
+            point_on_s1: hit.point,
+            point_on_s2: hit.point,
+            part_s1: 0,
+            part_s2: hit.part,
+        };
+        touching.insert(key, if entity < other { contact } else { contact.flipped() });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::fixtures::at;
+    use crate::collision::{BodyType, CollisionPlugin, CollisionStarted, Contacts};
+
+    #[test]
+    fn sweep_finds_time_of_impact() {
+        let wall = at(Collider::cuboid_from_half_extents(Vec3::new(0.05, 5.0, 5.0)), Vec3::ZERO);
+        //ended up well past the wall after moving 10 units
+        let spell = at(Collider::sphere_from_radius(0.1), Vec3::new(5.0, 0.0, 0.0));
+
+        let hit = sweep(&spell, &wall, Vec3::new(10.0, 0.0, 0.0)).expect("spell passed through the wall");
+        assert!((hit.distance - 4.85).abs() < 0.01, "distance was {}", hit.distance);
+        assert!(hit.normal.abs_diff_eq(Vec3::NEG_X, 0.01), "normal was {}", hit.normal);
+
+        assert!(sweep(&spell, &wall, Vec3::new(0.0, 0.0, 10.0)).is_none());
+    }
+
+    #[test]
+    fn ccd_registers_tunnelling_hit() {
+        let mut app = App::new();
+        app.add_plugins(CollisionPlugin);
+        app.world.spawn((
+            Collider::cuboid_from_half_extents(Vec3::new(0.05, 5.0, 5.0)),
+            GlobalTransform::IDENTITY,
+        ));
+        let spell = app.world.spawn((
+            Collider::sphere_from_radius(0.1),
+            GlobalTransform::from_translation(Vec3::new(-5.0, 0.0, 0.0)),
+            Ccd::default(),
+            BodyType::Kinematic,
+        )).id();
+
+        app.world.run_schedule(FixedUpdate);
+        *app.world.get_mut::<GlobalTransform>(spell).unwrap() = GlobalTransform::from_translation(Vec3::new(5.0, 0.0, 0.0));
+        app.world.run_schedule(FixedUpdate);
+
+        let started = app.world.resource::<Events<CollisionStarted>>();
+        let mut reader = started.get_reader();
+        assert_eq!(reader.iter(started).count(), 1);
+        assert!(app.world.resource::<Contacts>().touching(spell).next().is_some());
+    }
+}
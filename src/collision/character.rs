@@ -0,0 +1,272 @@
+use bevy::prelude::*;
+
+use super::{Collider, CollisionLayers, QueryFilter, RayHit, SpatialQuery};
+
+//how many surfaces a character can slide off in one tick before it gives up on the rest of the move
+pub const CHARACTER_MAX_SLIDES: u32 = 4;
+
+/**
+ * Moves a collider around the world without ever letting it sink into anything, for players and walking enemies.
+ * Set velocity each tick and move_characters shape-casts the collider along it, sliding along walls,
+ * stepping up anything lower than max_step_height and snapping down onto the ground when walking down slopes.
+ * Only colliders the entity's CollisionLayers filters let through are walked into, the entity shouldn't have a parent.
+ */
+#[derive(Component, Clone, Copy, Debug)]
+pub struct CharacterController {
+    pub velocity: Vec3,
+    pub max_step_height: f32,
+    //steepest slope in radians that can be walked up, anything steeper is treated like a wall
+    pub max_slope: f32,
+    //how far down the character gets pulled to stay on the ground when it walks over a drop or down a slope
+    pub snap_distance: f32,
+    //gap kept between the collider and whatever it's touching so the casts don't start off inside things
+    pub skin_width: f32,
+    //set by move_characters each tick
+    pub grounded: bool,
+    pub ground_normal: Vec3,
+    //true if something above stopped the character moving up
+    pub ceiling: bool,
+}
+
+impl Default for CharacterController {
+    fn default() -> Self {
+        return Self {
+            velocity: Vec3::ZERO,
+            max_step_height: 0.5,
+            max_slope: std::f32::consts::FRAC_PI_4,
+            snap_distance: 0.3,
+            skin_width: 0.02,
+            grounded: false,
+            ground_normal: Vec3::Y,
+            ceiling: false,
+        };
+    }
+}
+
+impl CharacterController {
+    pub fn walkable(&self, normal: Vec3) -> bool {
+        return normal.dot(Vec3::Y) >= self.max_slope.cos();
+    }
+}
+
+//one character's move, the casts all go from shape moved by an offset so the collider itself never has to be updated
+struct CharacterMove<'a, 'w, 's> {
+    spatial_query: &'a SpatialQuery<'w, 's>,
+    shape: &'a Collider,
+    filter: &'a QueryFilter,
+    controller: &'a CharacterController,
+}
+
+impl<'a, 'w, 's> CharacterMove<'a, 'w, 's> {
+    //stops skin_width short of whatever is in the way
+    fn cast(&self, offset: Vec3, direction: Vec3, distance: f32) -> Option<RayHit> {
+        return self.spatial_query.cast_shape(self.shape, offset, direction, distance + self.controller.skin_width, self.filter);
+    }
+
+    //moves as far as it can then slides the rest of the way along whatever it hit, returns where it ended up and if a wall got in the way
+    fn slide(&self, mut offset: Vec3, mut motion: Vec3, flat: bool) -> (Vec3, bool) {
+        let mut blocked = false;
+        for _ in 0..CHARACTER_MAX_SLIDES {
+            let Some(direction) = motion.try_normalize() else {
+                break;
+            };
+            let Some(hit) = self.cast(offset, direction, motion.length()) else {
+                offset += motion;
+                break;
+            };
+
+            let travel = (hit.distance - self.controller.skin_width).max(0.0);
+            offset += direction * travel;
+            motion -= direction * travel;
+
+            //walking into something too steep shouldn't lift the character up it, so only the sideways part of its normal counts
+            let mut normal = hit.normal;
+            if flat && !self.controller.walkable(normal) {
+                blocked = true;
+                normal = Vec3::new(normal.x, 0.0, normal.z).try_normalize().unwrap_or(normal);
+            }
+            motion -= normal * motion.dot(normal);
+        }
+        return (offset, blocked);
+    }
+
+    //lifts the character up, moves it sideways and puts it back down, None if it didn't land on something walkable
+    fn step_up(&self, offset: Vec3, motion: Vec3) -> Option<Vec3> {
+        let step = self.controller.max_step_height;
+        let lift = self.cast(offset, Vec3::Y, step).map_or(step, |hit| (hit.distance - self.controller.skin_width).max(0.0));
+        let (stepped, _) = self.slide(offset + Vec3::Y * lift, motion, true);
+        let landing = self.cast(stepped, Vec3::NEG_Y, lift)?;
+        if !self.controller.walkable(self.surface_normal(&landing)) {
+            return None;
+        }
+        return Some(stepped + Vec3::NEG_Y * (landing.distance - self.controller.skin_width).max(0.0));
+    }
+
+    //round bottoms touch edges at an angle, so the slope of whatever is underneath comes from a ray just inside the edge
+    fn surface_normal(&self, hit: &RayHit) -> Vec3 {
+        if self.controller.walkable(hit.normal) {
+            return hit.normal;
+        }
+        let skin = self.controller.skin_width;
+        let inwards = -Vec3::new(hit.normal.x, 0.0, hit.normal.z).normalize_or_zero() * skin;
+        let surface = self.spatial_query.cast_ray(hit.point + inwards + Vec3::Y * skin, Vec3::NEG_Y, skin * 2.0, self.filter);
+        return surface.map_or(hit.normal, |surface| surface.normal);
+    }
+}
+
+/**
+ * Moves every character by its velocity for one tick.
+ * Sideways movement happens first so steps and walls are dealt with, then up and down, then the ground underneath is checked.
+ */
+pub fn move_characters (
+    fixed_time: Res<FixedTime>,
+    spatial_query: SpatialQuery,
+    mut q_characters: Query<(Entity, &mut CharacterController, &Collider, &mut Transform, Option<&CollisionLayers>)>,
+) {
+    let dt = fixed_time.period.as_secs_f32();
+    for (entity, mut controller, collider, mut transform, layers) in q_characters.iter_mut() {
+        //the collider is only as up to date as the last tick, so it gets moved to where the character is now
+        let mut shape = collider.clone();
+        shape.set_transform(&GlobalTransform::from(*transform));
+        let filter = QueryFilter::from_mask(layers.map_or(CollisionLayers::ALL, |layers| layers.filters)).excluding(entity);
+        let character = CharacterMove { spatial_query: &spatial_query, shape: &shape, filter: &filter, controller: &controller };
+
+        let motion = controller.velocity * dt;
+        let horizontal = Vec3::new(motion.x, 0.0, motion.z);
+        let (mut offset, blocked) = character.slide(Vec3::ZERO, horizontal, true);
+        //stepping up is only worth it if it gets further than running into the wall did
+        if blocked && controller.grounded {
+            let progress = |offset: Vec3| Vec3::new(offset.x, 0.0, offset.z).length();
+            if let Some(stepped) = character.step_up(Vec3::ZERO, horizontal) {
+                if progress(stepped) > progress(offset) {
+                    offset = stepped;
+                }
+            }
+        }
+        let ceiling = motion.y > 0.0 && character.cast(offset, Vec3::Y, motion.y).is_some();
+        let (moved, _) = character.slide(offset, Vec3::new(0.0, motion.y, 0.0), false);
+        offset = moved;
+
+        //anything moving upwards is leaving the ground, otherwise it gets pulled down onto it if it was already there
+        let mut ground = None;
+        if controller.velocity.y <= 0.0 {
+            let reach = if controller.grounded { controller.snap_distance } else { controller.skin_width };
+            ground = character.cast(offset, Vec3::NEG_Y, reach)
+                .map(|hit| (hit, character.surface_normal(&hit)))
+                .filter(|(_, normal)| controller.walkable(*normal));
+        }
+        if let Some((hit, normal)) = ground {
+            //standing on an edge counts as being on the ground but only flat ground gets snapped to, pulling down onto an edge sinks into it
+            if controller.walkable(hit.normal) {
+                offset.y -= hit.distance - controller.skin_width;
+            }
+            controller.ground_normal = normal;
+        }
+        controller.grounded = ground.is_some();
+        controller.ceiling = ceiling;
+        transform.translation += offset;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::{BodyType, CollisionPlugin};
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins(CollisionPlugin);
+        app.world.spawn((Collider::half_space(), TransformBundle::default()));
+        return app;
+    }
+
+    //the collider is moved into place straight away because characters move before colliders get updated in a tick
+    fn spawn_box(app: &mut App, half_extents: Vec3, translation: Vec3) {
+        let transform = Transform::from_translation(translation);
+        let mut collider = Collider::cuboid_from_half_extents(half_extents);
+        collider.set_transform(&GlobalTransform::from(transform));
+        app.world.spawn((collider, TransformBundle::from_transform(transform)));
+    }
+
+    //a capsule 2 tall standing just above the floor
+    fn spawn_character(app: &mut App, translation: Vec3, velocity: Vec3) -> Entity {
+        let controller = CharacterController { velocity, grounded: true, ..default() };
+        return app.world.spawn((
+            Collider::capsule_from_radius_depth(0.5, 1.0),
+            controller,
+            BodyType::Kinematic,
+            TransformBundle::from_transform(Transform::from_translation(translation + Vec3::new(0.0, 1.0 + controller.skin_width, 0.0))),
+        )).id();
+    }
+
+    fn run(app: &mut App, ticks: u32) {
+        for _ in 0..ticks {
+            app.world.run_schedule(FixedUpdate);
+        }
+    }
+
+    #[test]
+    fn slides_along_walls() {
+        let mut app = app();
+        //a wall along z in front of the character
+        spawn_box(&mut app, Vec3::new(0.5, 5.0, 10.0), Vec3::new(2.0, 0.0, 0.0));
+        let character = spawn_character(&mut app, Vec3::ZERO, Vec3::new(5.0, 0.0, 5.0));
+
+        run(&mut app, 60);
+
+        let translation = app.world.get::<Transform>(character).unwrap().translation;
+        assert!(translation.x < 1.0 && translation.x > 0.9, "went through the wall to {}", translation);
+        assert!(translation.z > 4.5, "got stuck on the wall at {}", translation);
+        assert!(app.world.get::<CharacterController>(character).unwrap().grounded);
+    }
+
+    #[test]
+    fn steps_up_ledges() {
+        let mut app = app();
+        //a step 0.25 high and a wall 2 high further along
+        spawn_box(&mut app, Vec3::new(1.0, 0.125, 5.0), Vec3::new(2.0, 0.125, 0.0));
+        spawn_box(&mut app, Vec3::new(1.0, 1.0, 5.0), Vec3::new(6.0, 1.0, 0.0));
+        let character = spawn_character(&mut app, Vec3::ZERO, Vec3::new(2.0, 0.0, 0.0));
+
+        //long enough to walk off the far side of the step and up to the wall
+        run(&mut app, 150);
+
+        let translation = app.world.get::<Transform>(character).unwrap().translation;
+        assert!(translation.x > 4.0 && translation.x < 4.5, "ended up at {}", translation);
+        assert!(translation.y < 1.1, "climbed the wall to {}", translation);
+        assert!(app.world.get::<CharacterController>(character).unwrap().grounded);
+    }
+
+    #[test]
+    fn stops_at_ceilings() {
+        let mut app = app();
+        //a roof whose underside is 3.5 up
+        spawn_box(&mut app, Vec3::new(5.0, 0.5, 5.0), Vec3::new(0.0, 4.0, 0.0));
+        let character = spawn_character(&mut app, Vec3::ZERO, Vec3::new(0.0, 5.0, 0.0));
+
+        run(&mut app, 60);
+
+        let translation = app.world.get::<Transform>(character).unwrap().translation;
+        assert!(translation.y + 1.0 <= 3.5, "went through the roof to {}", translation);
+        assert!(app.world.get::<CharacterController>(character).unwrap().ceiling);
+    }
+
+    #[test]
+    fn snaps_down_slopes_and_knows_when_airborne() {
+        let mut app = app();
+        //a ramp going down 1 for every 4 along x
+        let heights = vec![vec![2.0, 1.0, 0.0]; 3];
+        app.world.spawn((Collider::heightfield(heights, Vec2::splat(8.0)), TransformBundle::default()));
+        let walker = spawn_character(&mut app, Vec3::new(-3.0, 1.8, 0.0), Vec3::new(2.0, 0.0, 0.0));
+        let jumper = spawn_character(&mut app, Vec3::new(20.0, 5.0, 0.0), Vec3::ZERO);
+
+        for _ in 0..100 {
+            run(&mut app, 1);
+            assert!(app.world.get::<CharacterController>(walker).unwrap().grounded, "walked off the ramp");
+        }
+
+        let controller = app.world.get::<CharacterController>(walker).unwrap();
+        assert!(controller.ground_normal.abs_diff_eq(Vec3::new(0.25, 1.0, 0.0).normalize(), 0.01), "ground normal was {}", controller.ground_normal);
+        assert!(!app.world.get::<CharacterController>(jumper).unwrap().grounded);
+    }
+}
@@ -0,0 +1,172 @@
+use std::borrow::Cow;
+
+use bevy::prelude::*;
+
+use super::{contact_manifold, contact_profiled, Collider, CollisionDiagnostics, Contact, ContactManifold, Shapes};
+
+//one convex piece of a compound collider, transform is relative to the entity the collider is on
+#[derive(Clone)]
+pub struct CompoundPart {
+    pub transform: Transform,
+    pub collider: Collider,
+}
+
+impl Collider {
+    /**
+     * Builds one collider out of several convex shapes so concave things like arches can be made.
+     * Compound parts get flattened so every part ends up being a single convex shape.
+     */
+    pub fn compound(parts: Vec<(Transform, Collider)>) -> Self {
+        let mut flattened = Vec::new();
+        for (transform, collider) in parts {
+            match collider.shape {
+                Shapes::Compound(inner) => {
+                    for part in inner {
+                        flattened.push(CompoundPart {
+                            transform: transform * part.transform,
+                            collider: part.collider,
+                        });
+                    }
+                },
+                _ => flattened.push(CompoundPart { transform, collider }),
+            }
+        }
+
+        return Self {
+            shape: Shapes::Compound(flattened),
+            local_points: Vec::new(),
+            transformed_points: Vec::new(),
+            scale: 1.0,
+        }
+    }
+
+    //the convex shapes that make up this collider, just itself unless it's a compound
+    pub fn parts(&self) -> Vec<&Collider> {
+        match self.shape {
+            Shapes::Compound(ref parts) => {
+                return parts.iter().map(|part| &part.collider).collect();
+            },
+            _ => {
+                return vec![self];
+            }
+        }
+    }
+}
+
+//the support of the whole compound is the best support out of all its parts, this gives the hull around every part
+//it's only used for bounding boxes since the narrow phase tests each part on its own
+pub(super) fn compound_support(parts: &Vec<CompoundPart>, d: Vec3) -> Vec3 {
+    let mut max_dot = f32::NEG_INFINITY;
+    let mut max_vec = Vec3::ZERO;
+    for part in parts {
+        let point = part.collider.support(d);
+        if point.dot(d) > max_dot {
+            max_dot = point.dot(d);
+            max_vec = point;
+        }
+    }
+    return max_vec;
+}
+
+/**
+ * Narrow phase for any two colliders.
+ * Compounds get every pair of parts tested and the deepest contact is kept, part_s1 and part_s2 say which parts it came from.
+ * Heightfields are tested a triangle at a time and only near the other collider.
+ */
+pub fn collide (
+    s1: &Collider,
+    s2: &Collider,
+) -> Option<Contact> {
+    return deepest_parts(s1, s2, &mut CollisionDiagnostics::default()).map(|(contact, _, _)| contact);
+}
+
+//same as collide but also builds the manifold, for compounds it only covers the deepest pair of parts
+pub fn collide_manifold (
+    s1: &Collider,
+    s2: &Collider,
+) -> Option<(Contact, ContactManifold)> {
+    return collide_manifold_profiled(s1, s2, &mut CollisionDiagnostics::default());
+}
+
+//collide_manifold that also counts the gjk and epa iterations into diagnostics
+pub(super) fn collide_manifold_profiled (
+    s1: &Collider,
+    s2: &Collider,
+    diagnostics: &mut CollisionDiagnostics,
+) -> Option<(Contact, ContactManifold)> {
+    let (contact, p1, p2) = deepest_parts(s1, s2, diagnostics)?;
+    return Some((contact, contact_manifold(&p1, &p2, &contact)));
+}
+
+fn deepest_parts<'a>(s1: &'a Collider, s2: &'a Collider, diagnostics: &mut CollisionDiagnostics) -> Option<(Contact, Cow<'a, Collider>, Cow<'a, Collider>)> {
+    let (mut parts1, mut parts2) = (s1.parts_near(&s2.aabb()), s2.parts_near(&s1.aabb()));
+    //single shapes don't need the extra bounding box checks
+    let single = parts1.len() == 1 && parts2.len() == 1;
+
+    let mut deepest: Option<(Contact, usize, usize)> = None;
+    for (n1, (i, p1)) in parts1.iter().enumerate() {
+        let aabb1 = p1.aabb();
+        for (n2, (j, p2)) in parts2.iter().enumerate() {
+            if !single && !aabb1.intersects(&p2.aabb()) {
+                continue;
+            }
+            let Some(mut hit) = contact_profiled(p1, p2, diagnostics) else {
+                continue;
+            };
+            hit.part_s1 = *i;
+            hit.part_s2 = *j;
+            if deepest.map_or(true, |(current, _, _)| hit.depth > current.depth) {
+                deepest = Some((hit, n1, n2));
+            }
+        }
+    }
+
+    let (contact, n1, n2) = deepest?;
+    return Some((contact, parts1.swap_remove(n1).1, parts2.swap_remove(n2).1));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::fixtures::sphere_at;
+
+    //an L made of a long bar along x and a short bar going up z from its end
+    fn l_shape(translation: Vec3) -> Collider {
+        let mut collider = Collider::compound(vec![
+            (Transform::from_xyz(0.0, 0.0, 0.0), Collider::cuboid_from_half_extents(Vec3::new(5.0, 1.0, 1.0))),
+            (Transform::from_xyz(4.0, 0.0, 5.0), Collider::cuboid_from_half_extents(Vec3::new(1.0, 1.0, 4.0))),
+        ]);
+        collider.set_transform(&GlobalTransform::from_translation(translation));
+        return collider;
+    }
+
+    #[test]
+    fn compound_reports_part_hit() {
+        let l = l_shape(Vec3::new(10.0, 0.0, 0.0));
+
+        let hit = collide(&l, &sphere_at(1.0, Vec3::new(10.0, 0.0, 1.5))).expect("sphere should hit the long bar");
+        assert_eq!(hit.part_s1, 0);
+
+        let hit = collide(&sphere_at(1.0, Vec3::new(12.5, 0.0, 6.0)), &l).expect("sphere should hit the short bar");
+        assert_eq!(hit.part_s2, 1);
+    }
+
+    #[test]
+    fn compound_inner_corner_is_empty() {
+        //inside the hull of the L but not inside either bar
+        let l = l_shape(Vec3::ZERO);
+        let sphere = sphere_at(1.0, Vec3::new(0.0, 0.0, 6.0));
+
+        assert!(l.aabb().intersects(&sphere.aabb()));
+        assert!(collide(&l, &sphere).is_none());
+    }
+
+    #[test]
+    fn nested_compounds_are_flattened() {
+        let nested = Collider::compound(vec![
+            (Transform::from_xyz(0.0, 3.0, 0.0), l_shape(Vec3::ZERO)),
+            (Transform::IDENTITY, Collider::sphere_from_radius(1.0)),
+        ]);
+        assert_eq!(nested.parts().len(), 3);
+    }
+}
@@ -0,0 +1,244 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use super::{convex_hull_edges, gjk, Collider, Contacts, GjkResult, Shapes};
+
+//how many straight lines make up the circles on cylinders and cones
+const RIM_SEGMENTS: usize = 24;
+//half-spaces go on forever so only a patch this many lines either side of their origin is drawn
+const HALF_SPACE_GRID_LINES: i32 = 10;
+const HALF_SPACE_GRID_SPACING: f32 = 2.0;
+//size of the dots drawn on contact points
+const CONTACT_POINT_RADIUS: f32 = 0.05;
+
+/**
+ * Settings for CollisionDebugPlugin, change them at runtime to turn parts of the drawing on and off.
+ * Nothing is drawn until enabled is set, either directly or by pressing toggle_key.
+ */
+#[derive(Resource)]
+pub struct CollisionDebug {
+    pub enabled: bool,
+    pub toggle_key: Option<KeyCode>,
+    //outline every collider where it is this frame
+    pub colliders: bool,
+    //dot on each side of every contact with an arrow along the normal
+    pub contacts: bool,
+    //draws the final gjk simplex for this pair while they overlap, it's in minkowski space so it sits around the world origin
+    pub simplex_pair: Option<(Entity, Entity)>,
+    pub collider_color: Color,
+    pub contact_color: Color,
+    pub simplex_color: Color,
+}
+
+impl Default for CollisionDebug {
+    fn default() -> Self {
+        return Self {
+            enabled: false,
+            toggle_key: Some(KeyCode::F1),
+            colliders: true,
+            contacts: true,
+            simplex_pair: None,
+            collider_color: Color::GREEN,
+            contact_color: Color::RED,
+            simplex_color: Color::YELLOW,
+        };
+    }
+}
+
+pub fn toggle_collision_debug (
+    mut debug: ResMut<CollisionDebug>,
+    keyboard_input: Option<Res<Input<KeyCode>>>,
+) {
+    let (Some(key), Some(keyboard_input)) = (debug.toggle_key, keyboard_input) else {
+        return;
+    };
+    if keyboard_input.just_pressed(key) {
+        debug.enabled = !debug.enabled;
+    }
+}
+
+//each collider's local points with the hull edges found from them, per part
+type HullEdgeCache = HashMap<Entity, (Vec<Vec<Vec3>>, Vec<Vec<(usize, usize)>>)>;
+
+//the hull edges are found once per collider and kept until its local points change,
+//Changed<Collider> can't be used because the transformed points are rewritten every tick
+pub fn draw_colliders (
+    debug: Res<CollisionDebug>,
+    mut gizmos: Gizmos,
+    q_colliders: Query<(Entity, &Collider)>,
+    mut hull_edges: Local<HullEdgeCache>,
+) {
+    if !debug.enabled || !debug.colliders {
+        return;
+    }
+    hull_edges.retain(|&entity, _| q_colliders.contains(entity));
+
+    for (entity, collider) in q_colliders.iter() {
+        let parts = collider.parts();
+        let (points, edges) = hull_edges.entry(entity).or_default();
+        let stale = points.len() != parts.len() || points.iter().zip(parts.iter()).any(|(points, part)| *points != part.local_points);
+        if stale {
+            *edges = parts.iter().map(|part| match part.shape {
+                Shapes::Polyhedron => convex_hull_edges(&part.local_points),
+                _ => Vec::new(),
+            }).collect();
+            *points = parts.iter().map(|part| part.local_points.clone()).collect();
+        }
+
+        for (part, edges) in parts.iter().zip(edges.iter()) {
+            draw_collider(&mut gizmos, part, edges, debug.collider_color);
+        }
+    }
+}
+
+fn draw_collider(gizmos: &mut Gizmos, collider: &Collider, hull_edges: &[(usize, usize)], color: Color) {
+    let points = &collider.transformed_points;
+    match collider.shape {
+        Shapes::Sphere => {
+            let centre = points[0] - collider.local_points[0];
+            gizmos.sphere(centre, Quat::IDENTITY, collider.local_points[0][0] * collider.scale, color);
+        },
+        Shapes::Polyhedron => {
+            for &(a, b) in hull_edges {
+                gizmos.line(points[a], points[b], color);
+            }
+        },
+        Shapes::Capsule => {
+            let (top, bottom) = (points[0], points[1]);
+            let radius = collider.local_points[2][0] * collider.scale;
+            gizmos.sphere(top, Quat::IDENTITY, radius, color);
+            gizmos.sphere(bottom, Quat::IDENTITY, radius, color);
+
+            //four lines down the sides join the two ends up
+            let axis = (top - bottom).try_normalize().unwrap_or(Vec3::Y);
+            let (u, v) = axis.any_orthonormal_pair();
+            for side in [u, -u, v, -v] {
+                gizmos.line(top + side * radius, bottom + side * radius, color);
+            }
+        },
+        Shapes::Cuboid => {
+            let centre = points[0];
+            let axes = [points[1] - centre, points[2] - centre, points[3] - centre];
+            //each edge runs along one axis with the other two pushed to either side
+            for i in 0..3 {
+                let (j, k) = ((i + 1) % 3, (i + 2) % 3);
+                for (sj, sk) in [(1.0, 1.0), (1.0, -1.0), (-1.0, 1.0), (-1.0, -1.0)] {
+                    let middle = centre + axes[j] * sj + axes[k] * sk;
+                    gizmos.line(middle - axes[i], middle + axes[i], color);
+                }
+            }
+        },
+        Shapes::Cylinder => {
+            let centre = points[0];
+            let (half_height, u, v) = (points[1] - centre, points[2] - centre, points[3] - centre);
+            draw_rim(gizmos, centre + half_height, u, v, color);
+            draw_rim(gizmos, centre - half_height, u, v, color);
+            for side in [u, -u, v, -v] {
+                gizmos.line(centre + half_height + side, centre - half_height + side, color);
+            }
+        },
+        Shapes::Cone => {
+            let centre = points[0];
+            let (half_height, u, v) = (points[1] - centre, points[2] - centre, points[3] - centre);
+            draw_rim(gizmos, centre - half_height, u, v, color);
+            for side in [u, -u, v, -v] {
+                gizmos.line(centre + half_height, centre - half_height + side, color);
+            }
+        },
+        //parts() already splits compounds up so this never gets reached
+        Shapes::Compound(_) => {},
+        Shapes::HalfSpace => {
+            //a patch of grid around the surface's origin with an arrow pointing out of it
+            let (origin, normal) = (points[0], (points[1] - points[0]).normalize_or_zero());
+            let (u, v) = normal.any_orthonormal_pair();
+            for i in -HALF_SPACE_GRID_LINES..=HALF_SPACE_GRID_LINES {
+                let offset = i as f32 * HALF_SPACE_GRID_SPACING;
+                let reach = HALF_SPACE_GRID_LINES as f32 * HALF_SPACE_GRID_SPACING;
+                gizmos.line(origin + u * offset - v * reach, origin + u * offset + v * reach, color);
+                gizmos.line(origin + v * offset - u * reach, origin + v * offset + u * reach, color);
+            }
+            gizmos.ray(origin, normal, color);
+        },
+        Shapes::Heightfield { rows, columns } => {
+            let grid = &points[2..];
+            for row in 0..rows {
+                gizmos.linestrip(grid[row * columns..(row + 1) * columns].iter().copied(), color);
+            }
+            for column in 0..columns {
+                gizmos.linestrip((0..rows).map(|row| grid[row * columns + column]), color);
+            }
+        },
+    }
+}
+
+//u and v are the two radius axes so scaled cylinders get an ellipse
+fn draw_rim(gizmos: &mut Gizmos, centre: Vec3, u: Vec3, v: Vec3, color: Color) {
+    let rim = (0..=RIM_SEGMENTS).map(|i| {
+        let angle = i as f32 / RIM_SEGMENTS as f32 * std::f32::consts::TAU;
+        return centre + u * angle.cos() + v * angle.sin();
+    });
+    gizmos.linestrip(rim, color);
+}
+
+pub fn draw_contacts (
+    debug: Res<CollisionDebug>,
+    mut gizmos: Gizmos,
+    contacts: Res<Contacts>,
+) {
+    if !debug.enabled || !debug.contacts {
+        return;
+    }
+
+    for (_, _, contact) in contacts.iter() {
+        gizmos.sphere(contact.point_on_s1, Quat::IDENTITY, CONTACT_POINT_RADIUS, debug.contact_color);
+        gizmos.sphere(contact.point_on_s2, Quat::IDENTITY, CONTACT_POINT_RADIUS, debug.contact_color);
+        //the line between the two points is the penetration, the arrow shows which way e2 gets pushed
+        gizmos.line(contact.point_on_s1, contact.point_on_s2, debug.contact_color);
+        gizmos.ray(contact.point_on_s2, contact.normal, debug.contact_color);
+    }
+}
+
+pub fn draw_simplex (
+    debug: Res<CollisionDebug>,
+    mut gizmos: Gizmos,
+    q_colliders: Query<&Collider>,
+) {
+    if !debug.enabled {
+        return;
+    }
+    let Some((e1, e2)) = debug.simplex_pair else {
+        return;
+    };
+    let Ok([s1, s2]) = q_colliders.get_many([e1, e2]) else {
+        return;
+    };
+    let GjkResult::Intersecting(simplex) = gjk(s1, s2) else {
+        return;
+    };
+
+    //every point of a tetrahedron is joined to every other one
+    for (i, a) in simplex.iter().enumerate() {
+        for b in &simplex[i + 1..] {
+            gizmos.line(a.point, b.point, debug.simplex_color);
+        }
+    }
+    //the simplex has to surround this for the pair to be touching
+    gizmos.sphere(Vec3::ZERO, Quat::IDENTITY, CONTACT_POINT_RADIUS, debug.simplex_color);
+}
+
+/**
+ * Draws colliders, contacts and gjk simplices with gizmos, see CollisionDebug for the settings.
+ * Needs CollisionPlugin and bevy's GizmoPlugin, which is part of DefaultPlugins.
+ */
+pub struct CollisionDebugPlugin;
+
+impl Plugin for CollisionDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CollisionDebug>()
+            .add_systems(Update, (
+                toggle_collision_debug,
+                (draw_colliders, draw_contacts, draw_simplex)
+                    .after(toggle_collision_debug),
+            ));
+    }
+}
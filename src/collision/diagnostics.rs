@@ -0,0 +1,200 @@
+use std::time::Duration;
+
+use bevy::diagnostic::{Diagnostic, DiagnosticId, Diagnostics, RegisterDiagnostic};
+use bevy::prelude::*;
+use bevy::utils::Instant;
+
+use super::GJK_MAX_ITERATIONS;
+
+//bucket i of the gjk histogram counts the runs that took up to 2^i iterations, the last one goes up to GJK_MAX_ITERATIONS
+pub const GJK_HISTOGRAM_BUCKETS: usize = 7;
+//how many frames of each value DiagnosticsStore keeps to average over
+pub const COLLISION_DIAGNOSTICS_HISTORY: usize = 20;
+
+/**
+ * What the collision pipeline did this frame, for working out where the time goes.
+ * Everything is added up over all the ticks in the frame, so a frame with two ticks shows twice the work and a frame with none shows nothing.
+ * The only exception is colliders, which is how many there were at the last tick.
+ * The same values are sent to bevy's DiagnosticsStore every frame so LogDiagnosticsPlugin prints them.
+ */
+#[derive(Resource, Default, Clone, Debug)]
+pub struct CollisionDiagnostics {
+    pub colliders: usize,
+    //pairs the broad phase handed on, not counting the ones where neither side can move
+    pub candidate_pairs: usize,
+    //candidate pairs that made it past the layer check and went through gjk
+    pub narrow_phase_tests: usize,
+    pub gjk_histogram: [usize; GJK_HISTOGRAM_BUCKETS],
+    //total over every epa run
+    pub epa_iterations: usize,
+    pub broad_phase_time: Duration,
+    pub narrow_phase_time: Duration,
+    pub ccd_time: Duration,
+    //working out which events to send and warm starting the manifolds
+    pub contact_time: Duration,
+    //bringing global transforms up to date and moving the colliders to match, before collision_update
+    pub transform_time: Duration,
+    //the whole of PhysicsSet::Solve, from waking bodies up to integrating them
+    pub solve_time: Duration,
+    //when the transform or solve stage currently being timed started
+    stage_start: Option<Instant>,
+}
+
+impl CollisionDiagnostics {
+    pub const COLLIDERS: DiagnosticId = DiagnosticId::from_u128(202758888035003910319602254527456438200);
+    pub const CANDIDATE_PAIRS: DiagnosticId = DiagnosticId::from_u128(161841467289248395747430554013812264069);
+    pub const NARROW_PHASE_TESTS: DiagnosticId = DiagnosticId::from_u128(165098384305176729460755949970830118524);
+    pub const EPA_ITERATIONS: DiagnosticId = DiagnosticId::from_u128(144483986030876199086882743470110987350);
+    pub const BROAD_PHASE_TIME: DiagnosticId = DiagnosticId::from_u128(273867600188872547480328592107755602484);
+    pub const NARROW_PHASE_TIME: DiagnosticId = DiagnosticId::from_u128(217727582546766583512548305990891289498);
+    pub const CCD_TIME: DiagnosticId = DiagnosticId::from_u128(263810167638689741187207079914644974669);
+    pub const CONTACT_TIME: DiagnosticId = DiagnosticId::from_u128(316999524493136652849173413381389636163);
+    pub const TRANSFORM_TIME: DiagnosticId = DiagnosticId::from_u128(48827322710036433162383255119492613162);
+    pub const SOLVE_TIME: DiagnosticId = DiagnosticId::from_u128(292415618987644195092422218107290709377);
+    pub const GJK_HISTOGRAM: [DiagnosticId; GJK_HISTOGRAM_BUCKETS] = [
+        DiagnosticId::from_u128(238886770367849602733191889418523487955),
+        DiagnosticId::from_u128(103111736288157381063888186905491491238),
+        DiagnosticId::from_u128(12966206889291297911617854459696729197),
+        DiagnosticId::from_u128(84842696283729985307354661889907545114),
+        DiagnosticId::from_u128(82800502852110255501067105799446791691),
+        DiagnosticId::from_u128(48237725801929814903633627918405612568),
+        DiagnosticId::from_u128(125702828213142535677765864366659419000),
+    ];
+
+    pub fn record_gjk(&mut self, iterations: u32) {
+        let bucket = iterations.max(1).next_power_of_two().trailing_zeros() as usize;
+        self.gjk_histogram[bucket.min(GJK_HISTOGRAM_BUCKETS - 1)] += 1;
+    }
+
+    pub fn gjk_runs(&self) -> usize {
+        return self.gjk_histogram.iter().sum();
+    }
+
+    fn stage_elapsed(&mut self) -> Duration {
+        return self.stage_start.take().map_or(Duration::ZERO, |start| start.elapsed());
+    }
+}
+
+//adds every value to the DiagnosticsStore, called by CollisionPlugin
+pub fn register_collision_diagnostics(app: &mut App) {
+    let history = COLLISION_DIAGNOSTICS_HISTORY;
+    app.register_diagnostic(Diagnostic::new(CollisionDiagnostics::COLLIDERS, "collision/colliders", history))
+        .register_diagnostic(Diagnostic::new(CollisionDiagnostics::CANDIDATE_PAIRS, "collision/candidate_pairs", history))
+        .register_diagnostic(Diagnostic::new(CollisionDiagnostics::NARROW_PHASE_TESTS, "collision/narrow_phase_tests", history))
+        .register_diagnostic(Diagnostic::new(CollisionDiagnostics::EPA_ITERATIONS, "collision/epa_iterations", history))
+        .register_diagnostic(Diagnostic::new(CollisionDiagnostics::BROAD_PHASE_TIME, "collision/broad_phase_time", history).with_suffix("ms"))
+        .register_diagnostic(Diagnostic::new(CollisionDiagnostics::NARROW_PHASE_TIME, "collision/narrow_phase_time", history).with_suffix("ms"))
+        .register_diagnostic(Diagnostic::new(CollisionDiagnostics::CCD_TIME, "collision/ccd_time", history).with_suffix("ms"))
+        .register_diagnostic(Diagnostic::new(CollisionDiagnostics::CONTACT_TIME, "collision/contact_time", history).with_suffix("ms"))
+        .register_diagnostic(Diagnostic::new(CollisionDiagnostics::TRANSFORM_TIME, "collision/transform_time", history).with_suffix("ms"))
+        .register_diagnostic(Diagnostic::new(CollisionDiagnostics::SOLVE_TIME, "collision/solve_time", history).with_suffix("ms"));
+    for (bucket, id) in CollisionDiagnostics::GJK_HISTOGRAM.into_iter().enumerate() {
+        let most = (1u32 << bucket).min(GJK_MAX_ITERATIONS);
+        app.register_diagnostic(Diagnostic::new(id, format!("collision/gjk_iterations<={}", most), history));
+    }
+}
+
+//keeps the collider count since it's how many there are rather than work done
+pub fn reset_collision_diagnostics (
+    mut collision_diagnostics: ResMut<CollisionDiagnostics>,
+) {
+    let colliders = collision_diagnostics.colliders;
+    *collision_diagnostics = CollisionDiagnostics { colliders, ..default() };
+}
+
+//the transform and solve stages are several systems each, so they're timed from a system at each end
+pub fn start_stage_timer (
+    mut collision_diagnostics: ResMut<CollisionDiagnostics>,
+) {
+    collision_diagnostics.stage_start = Some(Instant::now());
+}
+
+pub fn finish_transform_timer (
+    mut collision_diagnostics: ResMut<CollisionDiagnostics>,
+) {
+    let elapsed = collision_diagnostics.stage_elapsed();
+    collision_diagnostics.transform_time += elapsed;
+}
+
+pub fn finish_solve_timer (
+    mut collision_diagnostics: ResMut<CollisionDiagnostics>,
+) {
+    let elapsed = collision_diagnostics.stage_elapsed();
+    collision_diagnostics.solve_time += elapsed;
+}
+
+pub fn measure_collision_diagnostics (
+    collision_diagnostics: Res<CollisionDiagnostics>,
+    mut diagnostics: Diagnostics,
+) {
+    let values = &*collision_diagnostics;
+    let milliseconds = |time: Duration| time.as_secs_f64() * 1000.0;
+    diagnostics.add_measurement(CollisionDiagnostics::COLLIDERS, || values.colliders as f64);
+    diagnostics.add_measurement(CollisionDiagnostics::CANDIDATE_PAIRS, || values.candidate_pairs as f64);
+    diagnostics.add_measurement(CollisionDiagnostics::NARROW_PHASE_TESTS, || values.narrow_phase_tests as f64);
+    diagnostics.add_measurement(CollisionDiagnostics::EPA_ITERATIONS, || values.epa_iterations as f64);
+    diagnostics.add_measurement(CollisionDiagnostics::BROAD_PHASE_TIME, || milliseconds(values.broad_phase_time));
+    diagnostics.add_measurement(CollisionDiagnostics::NARROW_PHASE_TIME, || milliseconds(values.narrow_phase_time));
+    diagnostics.add_measurement(CollisionDiagnostics::CCD_TIME, || milliseconds(values.ccd_time));
+    diagnostics.add_measurement(CollisionDiagnostics::CONTACT_TIME, || milliseconds(values.contact_time));
+    diagnostics.add_measurement(CollisionDiagnostics::TRANSFORM_TIME, || milliseconds(values.transform_time));
+    diagnostics.add_measurement(CollisionDiagnostics::SOLVE_TIME, || milliseconds(values.solve_time));
+    for (bucket, id) in CollisionDiagnostics::GJK_HISTOGRAM.into_iter().enumerate() {
+        diagnostics.add_measurement(id, || values.gjk_histogram[bucket] as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::diagnostic::DiagnosticsStore;
+
+    use super::*;
+    use crate::collision::{BodyType, Collider, CollisionPlugin};
+
+    #[test]
+    fn gjk_runs_are_bucketed_by_iterations() {
+        let mut diagnostics = CollisionDiagnostics::default();
+        for iterations in [1, 2, 3, 4, 5, 40, GJK_MAX_ITERATIONS] {
+            diagnostics.record_gjk(iterations);
+        }
+        assert_eq!(diagnostics.gjk_histogram, [1, 1, 2, 1, 0, 0, 2]);
+        assert_eq!(diagnostics.gjk_runs(), 7);
+    }
+
+    #[test]
+    fn pipeline_is_counted_and_sent_to_the_store() {
+        let mut app = App::new();
+        app.add_plugins(CollisionPlugin);
+        let spawn_ball = |app: &mut App, x: f32| {
+            app.world.spawn((Collider::sphere_from_radius(1.0), BodyType::Kinematic, TransformBundle::from_transform(Transform::from_xyz(x, 0.0, 0.0))));
+        };
+        //two overlapping and one off on its own, kinematic so they stay where they are
+        spawn_ball(&mut app, 0.0);
+        spawn_ball(&mut app, 1.5);
+        spawn_ball(&mut app, 10.0);
+
+        app.world.run_schedule(PreUpdate);
+        app.world.run_schedule(FixedUpdate);
+        let values = app.world.resource::<CollisionDiagnostics>().clone();
+        assert_eq!((values.colliders, values.candidate_pairs, values.narrow_phase_tests), (3, 1, 1));
+        assert!(values.transform_time > Duration::ZERO && values.solve_time > Duration::ZERO);
+        assert_eq!(values.gjk_runs(), 1);
+        assert!(values.epa_iterations > 0);
+
+        //a new frame starts the counts again, then they're added up over every tick in it
+        app.world.run_schedule(PreUpdate);
+        assert_eq!(app.world.resource::<CollisionDiagnostics>().narrow_phase_tests, 0);
+        app.world.run_schedule(FixedUpdate);
+        app.world.run_schedule(FixedUpdate);
+        assert_eq!(app.world.resource::<CollisionDiagnostics>().narrow_phase_tests, 2);
+
+        app.world.run_schedule(PostUpdate);
+        let store = app.world.resource::<DiagnosticsStore>();
+        assert_eq!(store.get(CollisionDiagnostics::COLLIDERS).and_then(|diagnostic| diagnostic.value()), Some(3.0));
+        assert_eq!(store.get(CollisionDiagnostics::NARROW_PHASE_TESTS).and_then(|diagnostic| diagnostic.value()), Some(2.0));
+        for id in [CollisionDiagnostics::TRANSFORM_TIME, CollisionDiagnostics::SOLVE_TIME] {
+            let time = store.get(id).and_then(|diagnostic| diagnostic.value()).expect("stage time should be in the store");
+            assert!(time > 0.0, "stage took {}ms", time);
+        }
+    }
+}
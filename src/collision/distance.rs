@@ -0,0 +1,308 @@
+use bevy::prelude::*;
+
+use super::{half_space_height, support, Collider, ColliderAabb, Shapes, SupportPoint};
+
+//stop once the distance estimate improves by less than this
+pub const DISTANCE_TOLERANCE: f32 = 0.0001;
+pub const DISTANCE_MAX_ITERATIONS: u32 = 64;
+
+/**
+ * How far apart two colliders that aren't touching are.
+ * normal points from s1 towards s2 so point_on_s1 + normal * distance = point_on_s2.
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct Separation {
+    pub distance: f32,
+    pub normal: Vec3,
+    pub point_on_s1: Vec3,
+    pub point_on_s2: Vec3,
+}
+
+/**
+ * Returns the distance between the closest points of the two colliders.
+ * Returns None if they are intersecting, use contact for the penetration instead.
+ * Compounds return the closest pair of parts.
+ */
+pub fn gjk_distance (
+    s1: &Collider,
+    s2: &Collider,
+) -> Option<Separation> {
+    let mut closest: Option<Separation> = None;
+    for (_, p1) in s1.parts_near(&ColliderAabb::INFINITE) {
+        for (_, p2) in s2.parts_near(&ColliderAabb::INFINITE) {
+            let separation = convex_distance(&p1, &p2)?;
+            if closest.map_or(true, |current| separation.distance < current.distance) {
+                closest = Some(separation);
+            }
+        }
+    }
+    return closest;
+}
+
+fn convex_distance (
+    s1: &Collider,
+    s2: &Collider,
+) -> Option<Separation> {
+    return convex_distance_offset(s1, s2, Vec3::ZERO);
+}
+
+//same idea as gjk except the simplex is always shrunk down to the part nearest the origin instead of just the side facing it
+//s1 is treated as if it had been moved by offset, which lets casts slide a shape along without rebuilding its points
+pub(super) fn convex_distance_offset (
+    s1: &Collider,
+    s2: &Collider,
+    offset: Vec3,
+) -> Option<Separation> {
+    //the closest point to a half-space is just whichever point is lowest under its normal
+    match (&s1.shape, &s2.shape) {
+        (Shapes::HalfSpace, Shapes::HalfSpace) => return None,
+        (_, Shapes::HalfSpace) => {
+            let (height, lowest, normal) = half_space_height(s2, s1, offset);
+            if height < DISTANCE_TOLERANCE {
+                return None;
+            }
+            return Some(Separation { distance: height, normal: -normal, point_on_s1: lowest, point_on_s2: lowest - normal * height });
+        },
+        (Shapes::HalfSpace, _) => {
+            //moving the half-space by offset is the same as moving s2 the other way
+            let (height, lowest, normal) = half_space_height(s1, s2, -offset);
+            if height < DISTANCE_TOLERANCE {
+                return None;
+            }
+            let lowest = lowest + offset;
+            return Some(Separation { distance: height, normal, point_on_s1: lowest - normal * height, point_on_s2: lowest });
+        },
+        _ => {},
+    }
+
+    let shifted_support = |d: Vec3| {
+        let mut point = support(s1, s2, &d);
+        point.point += offset;
+        point.on_s1 += offset;
+        return point;
+    };
+
+    let mut simplex = vec![shifted_support(Vec3::ONE.normalize())];
+    let mut weights = vec![1.0];
+    let mut closest = simplex[0].point;
+
+    for _ in 0..DISTANCE_MAX_ITERATIONS {
+        let distance = closest.length();
+        if distance < DISTANCE_TOLERANCE {
+            return None;
+        }
+
+        //the new point can't get any closer to the origin than its projection onto the current direction
+        let d = -closest / distance;
+        let p = shifted_support(d);
+        if distance - p.point.dot(-d) < DISTANCE_TOLERANCE || simplex.iter().any(|point| point.point == p.point) {
+            break;
+        }
+
+        let mut grown = simplex.clone();
+        grown.push(p);
+        let Some(result) = closest_on_simplex(&grown) else {
+            //the origin is inside the tetrahedron
+            return None;
+        };
+        //long thin simplices lose precision and can end up further away, the last estimate is better than that
+        if result.0.length() >= distance {
+            break;
+        }
+        (closest, simplex, weights) = result;
+    }
+
+    let point_on_s1 = simplex.iter().zip(&weights).fold(Vec3::ZERO, |total, (point, weight)| total + point.on_s1 * *weight);
+    let point_on_s2 = simplex.iter().zip(&weights).fold(Vec3::ZERO, |total, (point, weight)| total + point.on_s2 * *weight);
+    let distance = closest.length();
+    if distance < DISTANCE_TOLERANCE {
+        return None;
+    }
+
+    return Some(Separation {
+        distance,
+        normal: -closest / distance,
+        point_on_s1,
+        point_on_s2,
+    });
+}
+
+//closest point to the origin on the simplex, along with the smallest part of the simplex that contains it and the weights that make it
+//gjk's handle_simplex only keeps the side facing the origin and never works out where on it the closest point is, this needs the weights to find the points on each shape
+fn closest_on_simplex(simplex: &Vec<SupportPoint>) -> Option<(Vec3, Vec<SupportPoint>, Vec<f32>)> {
+    let weights: Vec<f32> = match simplex.len() {
+        1 => vec![1.0],
+        2 => closest_on_segment(simplex[0].point, simplex[1].point).to_vec(),
+        3 => closest_on_triangle(simplex[0].point, simplex[1].point, simplex[2].point).to_vec(),
+        _ => closest_on_tetrahedron(simplex)?,
+    };
+
+    let mut reduced = Vec::new();
+    let mut reduced_weights = Vec::new();
+    let mut closest = Vec3::ZERO;
+    for (point, weight) in simplex.iter().zip(weights) {
+        //points with no weight aren't needed to describe the closest point any more
+        if weight > 0.0 {
+            closest += point.point * weight;
+            reduced.push(*point);
+            reduced_weights.push(weight);
+        }
+    }
+    return Some((closest, reduced, reduced_weights));
+}
+
+fn closest_on_segment(a: Vec3, b: Vec3) -> [f32; 2] {
+    let ab = b - a;
+    let t = (-a).dot(ab) / ab.length_squared().max(f32::EPSILON);
+    let t = t.clamp(0.0, 1.0);
+    return [1.0 - t, t];
+}
+
+//works out which region of the triangle the origin is closest to, from real time collision detection
+fn closest_on_triangle(a: Vec3, b: Vec3, c: Vec3) -> [f32; 3] {
+    let (ab, ac, ap) = (b - a, c - a, -a);
+    let (d1, d2) = (ab.dot(ap), ac.dot(ap));
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return [1.0, 0.0, 0.0];
+    }
+
+    let bp = -b;
+    let (d3, d4) = (ab.dot(bp), ac.dot(bp));
+    if d3 >= 0.0 && d4 <= d3 {
+        return [0.0, 1.0, 0.0];
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        let v = d1 / (d1 - d3);
+        return [1.0 - v, v, 0.0];
+    }
+
+    let cp = -c;
+    let (d5, d6) = (ab.dot(cp), ac.dot(cp));
+    if d6 >= 0.0 && d5 <= d6 {
+        return [0.0, 0.0, 1.0];
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        let w = d2 / (d2 - d6);
+        return [1.0 - w, 0.0, w];
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+        return [0.0, 1.0 - w, w];
+    }
+
+    let denom = 1.0 / (va + vb + vc);
+    let (v, w) = (vb * denom, vc * denom);
+    return [1.0 - v - w, v, w];
+}
+
+//tries every face the origin is in front of and keeps the closest, None if the origin is inside
+fn closest_on_tetrahedron(simplex: &Vec<SupportPoint>) -> Option<Vec<f32>> {
+    let points: Vec<Vec3> = simplex.iter().map(|point| point.point).collect();
+    let faces = [[0, 1, 2, 3], [0, 2, 3, 1], [0, 3, 1, 2], [1, 3, 2, 0]];
+
+    //a flat tetrahedron has no inside for the origin to be in, which happens when both shapes have flat sides facing each other,
+    //so the closest point is just on whichever of its faces is nearest
+    let base = (points[1] - points[0]).cross(points[2] - points[0]);
+    let flat = base.dot(points[3] - points[0]).abs() <= DISTANCE_TOLERANCE * base.length();
+
+    let mut best: Option<(f32, Vec<f32>)> = None;
+    for [a, b, c, opposite] in faces {
+        let normal = (points[b] - points[a]).cross(points[c] - points[a]);
+        //the origin has to be on the other side of the face to the fourth point
+        let origin_side = normal.dot(-points[a]);
+        let opposite_side = normal.dot(points[opposite] - points[a]);
+        if !flat && origin_side * opposite_side >= 0.0 {
+            continue;
+        }
+
+        let face_weights = closest_on_triangle(points[a], points[b], points[c]);
+        let closest = points[a] * face_weights[0] + points[b] * face_weights[1] + points[c] * face_weights[2];
+        if best.as_ref().map_or(true, |(distance, _)| closest.length_squared() < *distance) {
+            let mut weights = vec![0.0; 4];
+            weights[a] = face_weights[0];
+            weights[b] = face_weights[1];
+            weights[c] = face_weights[2];
+            best = Some((closest.length_squared(), weights));
+        }
+    }
+    return best.map(|(_, weights)| weights);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::fixtures::at;
+
+    #[test]
+    fn sphere_sphere_distance() {
+        let s1 = at(Collider::sphere_from_radius(1.0), Vec3::ZERO);
+        let s2 = at(Collider::sphere_from_radius(1.0), Vec3::new(5.0, 0.0, 0.0));
+
+        let result = gjk_distance(&s1, &s2).expect("spheres are apart");
+        assert!((result.distance - 3.0).abs() < 0.001, "distance was {}", result.distance);
+        //curved shapes only get close to the exact points, the distance converges much faster than the direction
+        assert!(result.normal.abs_diff_eq(Vec3::X, 0.01), "normal was {}", result.normal);
+        assert!(result.point_on_s1.abs_diff_eq(Vec3::X, 0.01), "s1 point was {}", result.point_on_s1);
+        assert!(result.point_on_s2.abs_diff_eq(Vec3::new(4.0, 0.0, 0.0), 0.01), "s2 point was {}", result.point_on_s2);
+    }
+
+    #[test]
+    fn cuboid_corner_to_sphere() {
+        let cube = at(Collider::cuboid_from_half_extents(Vec3::ONE), Vec3::ZERO);
+        let sphere = at(Collider::sphere_from_radius(1.0), Vec3::splat(3.0));
+
+        //corner to centre is 2 * sqrt(3), minus the radius
+        let result = gjk_distance(&cube, &sphere).expect("shapes are apart");
+        let expected = 2.0 * 3.0_f32.sqrt() - 1.0;
+        assert!((result.distance - expected).abs() < 0.001, "distance was {}", result.distance);
+        assert!(result.point_on_s1.abs_diff_eq(Vec3::ONE, 0.001), "s1 point was {}", result.point_on_s1);
+    }
+
+    #[test]
+    fn cuboid_face_to_cuboid() {
+        let c1 = at(Collider::cuboid_from_half_extents(Vec3::ONE), Vec3::ZERO);
+        let c2 = at(Collider::cuboid_from_half_extents(Vec3::ONE), Vec3::new(0.5, 4.0, -0.25));
+
+        let result = gjk_distance(&c1, &c2).expect("cubes are apart");
+        assert!((result.distance - 2.0).abs() < 0.001, "distance was {}", result.distance);
+        assert!(result.normal.abs_diff_eq(Vec3::Y, 0.001), "normal was {}", result.normal);
+        assert!((result.point_on_s1.y - 1.0).abs() < 0.001 && (result.point_on_s2.y - 3.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn flat_faces_facing_each_other() {
+        //all four points of the simplex end up in the plane between the two faces
+        let c1 = at(Collider::cuboid_from_half_extents(Vec3::new(2.4605262, 1.5975981, 2.9513152)), Vec3::ZERO);
+        let c2 = at(Collider::cuboid_from_half_extents(Vec3::new(1.9399271, 0.9015886, 3.0155075)), Vec3::new(-2.7458014, 2.522216, 0.23284669));
+
+        let result = gjk_distance(&c1, &c2).expect("cubes are apart");
+        let expected = 2.522216 - 1.5975981 - 0.9015886;
+        assert!((result.distance - expected).abs() < 0.001, "distance was {}", result.distance);
+    }
+
+    #[test]
+    fn capsule_beside_cuboid_edge() {
+        let half_extents = Vec3::new(1.405706, 4.621723, 0.39484876);
+        let cube = at(Collider::cuboid_from_half_extents(half_extents), Vec3::ZERO);
+        let capsule = at(Collider::capsule_from_radius_depth(0.5, 1.0), Vec3::new(-2.0239794, 1.2286115, -0.75725436));
+
+        //the thin triangles along the edge used to make the estimate wander off instead of getting closer
+        let result = gjk_distance(&cube, &capsule).expect("shapes are apart");
+        let expected = Vec2::new(2.0239794 - half_extents.x, 0.75725436 - half_extents.z).length() - 0.5;
+        assert!((result.distance - expected).abs() < 0.001, "distance was {} not {}", result.distance, expected);
+    }
+
+    #[test]
+    fn intersecting_has_no_distance() {
+        let c1 = at(Collider::cuboid_from_half_extents(Vec3::ONE), Vec3::ZERO);
+        let c2 = at(Collider::cuboid_from_half_extents(Vec3::ONE), Vec3::new(1.0, 0.5, 0.0));
+
+        assert!(gjk_distance(&c1, &c2).is_none());
+    }
+}
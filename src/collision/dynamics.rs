@@ -0,0 +1,415 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use super::{BodyType, Collider, Contacts, Sensor, Shapes};
+
+//how many times the solver goes over every contact point each step, more is stiffer but slower
+pub const SOLVER_ITERATIONS: u32 = 8;
+//things hitting slower than this don't bounce, otherwise resting bodies would never settle
+pub const RESTITUTION_THRESHOLD: f32 = 1.0;
+//bodies are left sunk this far into each other so resting contacts don't flicker on and off every tick
+pub const PENETRATION_SLOP: f32 = 0.01;
+//fraction of the rest of the overlap pushed out each tick, all of it at once makes stacks jitter
+pub const PENETRATION_CORRECTION: f32 = 0.2;
+//used for the other side of a contact when it has no RigidBody, like the floor or a wall
+pub const DEFAULT_FRICTION: f32 = 0.5;
+pub const DEFAULT_RESTITUTION: f32 = 0.0;
+
+//acceleration applied to every dynamic rigid body
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct Gravity(pub Vec3);
+
+impl Default for Gravity {
+    fn default() -> Self {
+        return Self(Vec3::new(0.0, -9.81, 0.0));
+    }
+}
+
+/**
+ * Gives a collider mass and velocity so it gets moved by gravity and knocked around by whatever it hits.
+ * Only bodies with BodyType::Dynamic are moved, static and kinematic ones act as if they were infinitely heavy.
+ * The centre of mass is the entity's origin and inertia is around the entity's local axes.
+ * Bodies that stay still for a while fall asleep and stop being simulated until something touches them, see update_sleep.
+ */
+#[derive(Component, Clone, Copy, Debug)]
+pub struct RigidBody {
+    pub mass: f32,
+    pub inertia: Vec3,
+    pub linear_velocity: Vec3,
+    pub angular_velocity: Vec3,
+    //0 slides forever, 1 grips, the two bodies' values get multiplied together and square rooted
+    pub friction: f32,
+    //0 stops dead, 1 bounces back just as fast, the bouncier of the two bodies is used
+    pub restitution: f32,
+    pub gravity_scale: f32,
+    //set by update_sleep, giving a sleeping body some velocity wakes it up again
+    pub sleeping: bool,
+    //how long it has been moving slowly enough to fall asleep
+    pub(super) sleep_time: f32,
+}
+
+impl RigidBody {
+    //inertia is worked out from the collider's shape as if it was solid all the way through
+    pub fn new(mass: f32, collider: &Collider) -> Self {
+        return Self {
+            mass,
+            inertia: collider.inertia(mass),
+            linear_velocity: Vec3::ZERO,
+            angular_velocity: Vec3::ZERO,
+            friction: DEFAULT_FRICTION,
+            restitution: DEFAULT_RESTITUTION,
+            gravity_scale: 1.0,
+            sleeping: false,
+            sleep_time: 0.0,
+        };
+    }
+
+    pub fn with_velocity(mut self, velocity: Vec3) -> Self {
+        self.linear_velocity = velocity;
+        return self;
+    }
+
+    pub fn with_friction(mut self, friction: f32) -> Self {
+        self.friction = friction;
+        return self;
+    }
+
+    pub fn with_restitution(mut self, restitution: f32) -> Self {
+        self.restitution = restitution;
+        return self;
+    }
+
+    pub fn with_gravity_scale(mut self, gravity_scale: f32) -> Self {
+        self.gravity_scale = gravity_scale;
+        return self;
+    }
+
+    //a body with no mass can't be pushed rather than being sent off at infinite speed
+    pub fn inverse_mass(&self) -> f32 {
+        return if self.mass > 0.0 { self.mass.recip() } else { 0.0 };
+    }
+
+    //pushes the body as if it had been hit at point (in world space), for knockback and explosions
+    pub fn apply_impulse(&mut self, impulse: Vec3, point: Vec3, transform: &GlobalTransform) {
+        let (_, rotation, centre) = transform.to_scale_rotation_translation();
+        self.linear_velocity += impulse * self.inverse_mass();
+        self.angular_velocity += inverse_inertia(self.inertia, rotation, (point - centre).cross(impulse));
+    }
+}
+
+impl Collider {
+    //moments of inertia around each local axis, spheres are exact and everything else is treated as a box the size of its local bounds
+    pub fn inertia(&self, mass: f32) -> Vec3 {
+        if let Shapes::Sphere = self.shape {
+            let radius = self.local_points[0].x * self.scale;
+            return Vec3::splat(0.4 * mass * radius * radius);
+        }
+
+        let mut local = self.clone();
+        local.set_transform(&GlobalTransform::IDENTITY);
+        let aabb = local.aabb();
+        let size = (aabb.max - aabb.min).min(Vec3::splat(f32::MAX));
+        let squared = size * size;
+        return Vec3::new(squared.y + squared.z, squared.x + squared.z, squared.x + squared.y) * mass / 12.0;
+    }
+}
+
+//applies the world space inverse inertia to v, inertia is stored around the body's own axes so v gets turned into them and back
+fn inverse_inertia(inertia: Vec3, rotation: Quat, v: Vec3) -> Vec3 {
+    let local = rotation.inverse() * v;
+    let inverse = Vec3::select(inertia.cmpgt(Vec3::ZERO), inertia.recip(), Vec3::ZERO);
+    return rotation * (local * inverse);
+}
+
+//a copy of everything the solver needs about one body so it doesn't have to go through queries for every point
+struct SolverBody {
+    inverse_mass: f32,
+    inertia: Vec3,
+    rotation: Quat,
+    centre: Vec3,
+    linear_velocity: Vec3,
+    angular_velocity: Vec3,
+    friction: f32,
+    restitution: f32,
+}
+
+impl SolverBody {
+    fn velocity_at(&self, point: Vec3) -> Vec3 {
+        return self.linear_velocity + self.angular_velocity.cross(point - self.centre);
+    }
+
+    fn apply(&mut self, impulse: Vec3, point: Vec3) {
+        if self.inverse_mass == 0.0 {
+            return;
+        }
+        self.linear_velocity += impulse * self.inverse_mass;
+        self.angular_velocity += inverse_inertia(self.inertia, self.rotation, (point - self.centre).cross(impulse));
+    }
+
+    //how much the velocity at point changes along direction for each unit of impulse along it
+    fn response(&self, point: Vec3, direction: Vec3) -> f32 {
+        if self.inverse_mass == 0.0 {
+            return 0.0;
+        }
+        let arm = point - self.centre;
+        let spin = inverse_inertia(self.inertia, self.rotation, arm.cross(direction));
+        return self.inverse_mass + spin.cross(arm).dot(direction);
+    }
+}
+
+pub fn apply_gravity (
+    gravity: Res<Gravity>,
+    fixed_time: Res<FixedTime>,
+    mut q_bodies: Query<(&mut RigidBody, &BodyType)>,
+) {
+    for (mut body, body_type) in q_bodies.iter_mut() {
+        if *body_type == BodyType::Dynamic && !body.sleeping {
+            let scale = body.gravity_scale;
+            body.linear_velocity += gravity.0 * scale * fixed_time.period.as_secs_f32();
+        }
+    }
+}
+
+/**
+ * Sequential impulse solver, works out the push at every manifold point that stops the bodies moving into each other.
+ * It starts from last frame's impulses (see ManifoldPoint) and saves the new ones back for next frame.
+ * Restitution makes the bodies bounce apart and friction stops them sliding along each other.
+ * Overlap is fixed by pushing the bodies apart a bit faster than they would otherwise move, so resolve_contacts leaves rigid bodies alone.
+ */
+pub fn solve_contacts (
+    fixed_time: Res<FixedTime>,
+    mut contacts: ResMut<Contacts>,
+    mut q_bodies: Query<(Entity, &mut RigidBody, &GlobalTransform, Option<&BodyType>)>,
+    q_sensors: Query<(), With<Sensor>>,
+) {
+    let mut bodies: HashMap<Entity, SolverBody> = HashMap::default();
+    for (entity, body, transform, body_type) in q_bodies.iter() {
+        let (_, rotation, centre) = transform.to_scale_rotation_translation();
+        //sleeping bodies only touch things that aren't moving so they can be treated like the floor
+        let dynamic = body_type == Some(&BodyType::Dynamic) && !body.sleeping;
+        bodies.insert(entity, SolverBody {
+            inverse_mass: if dynamic { body.inverse_mass() } else { 0.0 },
+            inertia: body.inertia,
+            rotation,
+            centre,
+            linear_velocity: body.linear_velocity,
+            angular_velocity: body.angular_velocity,
+            friction: body.friction,
+            restitution: body.restitution,
+        });
+    }
+    //anything without a rigid body never moves
+    let immovable = SolverBody {
+        inverse_mass: 0.0,
+        inertia: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        centre: Vec3::ZERO,
+        linear_velocity: Vec3::ZERO,
+        angular_velocity: Vec3::ZERO,
+        friction: DEFAULT_FRICTION,
+        restitution: DEFAULT_RESTITUTION,
+    };
+
+    let mut pairs: Vec<(Entity, Entity)> = contacts.manifolds.keys().copied().filter(|&(e1, e2)| {
+        let moves = |entity| bodies.get(&entity).map_or(false, |body: &SolverBody| body.inverse_mass > 0.0);
+        return (moves(e1) || moves(e2)) && !q_sensors.contains(e1) && !q_sensors.contains(e2);
+    }).collect();
+    //hashmap order changes from run to run, a fixed order keeps the simulation repeatable
+    pairs.sort();
+
+    //the bounce speed has to come from the velocities before any impulses are applied
+    let dt = fixed_time.period.as_secs_f32();
+    let mut targets: HashMap<(Entity, Entity), Vec<f32>> = HashMap::default();
+    for key in &pairs {
+        let manifold = &contacts.manifolds[key];
+        let (b1, b2) = (bodies.get(&key.0).unwrap_or(&immovable), bodies.get(&key.1).unwrap_or(&immovable));
+        let restitution = b1.restitution.max(b2.restitution);
+        targets.insert(*key, manifold.points.iter().map(|point| {
+            let closing = (b2.velocity_at(point.point_on_s2) - b1.velocity_at(point.point_on_s1)).dot(manifold.normal);
+            let bounce = if closing < -RESTITUTION_THRESHOLD { -closing * restitution } else { 0.0 };
+            let correction = (point.depth - PENETRATION_SLOP).max(0.0) * PENETRATION_CORRECTION / dt;
+            return bounce.max(correction);
+        }).collect());
+    }
+
+    //the impulses from last frame are usually close to right so they get applied straight away
+    for key in &pairs {
+        let manifold = &contacts.manifolds[key];
+        for point in &manifold.points {
+            let impulse = manifold.normal * point.normal_impulse + point.tangent_impulse;
+            push(&mut bodies, *key, point.point_on_s1, point.point_on_s2, impulse);
+        }
+    }
+
+    for _ in 0..SOLVER_ITERATIONS {
+        for key in &pairs {
+            let manifold = contacts.manifolds.get_mut(key).unwrap();
+            let normal = manifold.normal;
+            for (point, target) in manifold.points.iter_mut().zip(&targets[key]) {
+                let (b1, b2) = (bodies.get(&key.0).unwrap_or(&immovable), bodies.get(&key.1).unwrap_or(&immovable));
+                let friction = (b1.friction * b2.friction).sqrt();
+                let relative = b2.velocity_at(point.point_on_s2) - b1.velocity_at(point.point_on_s1);
+
+                //the total push along the normal can only ever push the bodies apart, never pull them together
+                let response = b1.response(point.point_on_s1, normal) + b2.response(point.point_on_s2, normal);
+                if response <= 0.0 {
+                    continue;
+                }
+                let accumulated = (point.normal_impulse + (target - relative.dot(normal)) / response).max(0.0);
+                let normal_change = accumulated - point.normal_impulse;
+                point.normal_impulse = accumulated;
+
+                //friction opposes sliding but can't push harder than the normal impulse allows
+                let sliding = relative - normal * relative.dot(normal);
+                let mut tangent_change = Vec3::ZERO;
+                if let Some(tangent) = sliding.try_normalize() {
+                    let response = b1.response(point.point_on_s1, tangent) + b2.response(point.point_on_s2, tangent);
+                    let accumulated = (point.tangent_impulse - tangent * sliding.length() / response).clamp_length_max(friction * point.normal_impulse);
+                    tangent_change = accumulated - point.tangent_impulse;
+                    point.tangent_impulse = accumulated;
+                }
+
+                push(&mut bodies, *key, point.point_on_s1, point.point_on_s2, normal * normal_change + tangent_change);
+            }
+        }
+    }
+
+    for (entity, mut body, _, _) in q_bodies.iter_mut() {
+        if let Some(solved) = bodies.get(&entity) {
+            if solved.inverse_mass > 0.0 {
+                body.linear_velocity = solved.linear_velocity;
+                body.angular_velocity = solved.angular_velocity;
+            }
+        }
+    }
+}
+
+//impulse pushes e2 along it and e1 the opposite way
+fn push(bodies: &mut HashMap<Entity, SolverBody>, (e1, e2): (Entity, Entity), point_on_s1: Vec3, point_on_s2: Vec3, impulse: Vec3) {
+    if let Some(body) = bodies.get_mut(&e1) {
+        body.apply(-impulse, point_on_s1);
+    }
+    if let Some(body) = bodies.get_mut(&e2) {
+        body.apply(impulse, point_on_s2);
+    }
+}
+
+//moves and turns every dynamic body by its velocity, the colliders catch up next time apply_transform_collider runs
+pub fn integrate_bodies (
+    fixed_time: Res<FixedTime>,
+    mut q_bodies: Query<(&RigidBody, &mut Transform, &BodyType)>,
+) {
+    let dt = fixed_time.period.as_secs_f32();
+    for (body, mut transform, body_type) in q_bodies.iter_mut() {
+        if *body_type != BodyType::Dynamic || body.sleeping {
+            continue;
+        }
+        transform.translation += body.linear_velocity * dt;
+        transform.rotation = (Quat::from_scaled_axis(body.angular_velocity * dt) * transform.rotation).normalize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::CollisionPlugin;
+
+    //ticks at bevy's default rate of 60 a second
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins(CollisionPlugin);
+        return app;
+    }
+
+    fn spawn_body(app: &mut App, collider: Collider, translation: Vec3, body: RigidBody) -> Entity {
+        return app.world.spawn((
+            collider,
+            body,
+            BodyType::Dynamic,
+            TransformBundle::from_transform(Transform::from_translation(translation)),
+        )).id();
+    }
+
+    fn spawn_floor(app: &mut App) -> Entity {
+        return app.world.spawn((Collider::cuboid_from_half_extents(Vec3::new(50.0, 1.0, 50.0)), TransformBundle::default())).id();
+    }
+
+    #[test]
+    fn bodies_fall_under_gravity() {
+        let mut app = app();
+        let ball = Collider::sphere_from_radius(1.0);
+        let body = RigidBody::new(1.0, &ball);
+        let ball = spawn_body(&mut app, ball, Vec3::new(0.0, 100.0, 0.0), body);
+
+        for _ in 0..60 {
+            app.world.run_schedule(FixedUpdate);
+        }
+
+        let velocity = app.world.get::<RigidBody>(ball).unwrap().linear_velocity;
+        assert!((velocity.y + 9.81).abs() < 0.01, "velocity was {}", velocity);
+        let height = app.world.get::<Transform>(ball).unwrap().translation.y;
+        assert!((height - (100.0 - 0.5 * 9.81)).abs() < 0.2, "height was {}", height);
+    }
+
+    #[test]
+    fn bouncy_ball_bounces() {
+        let mut app = app();
+        spawn_floor(&mut app);
+        let ball = Collider::sphere_from_radius(1.0);
+        let body = RigidBody::new(1.0, &ball).with_restitution(1.0).with_gravity_scale(0.0).with_velocity(Vec3::new(0.0, -5.0, 0.0));
+        let ball = spawn_body(&mut app, ball, Vec3::new(0.0, 1.95, 0.0), body);
+
+        app.world.run_schedule(FixedUpdate);
+
+        let velocity = app.world.get::<RigidBody>(ball).unwrap().linear_velocity;
+        assert!((velocity.y - 5.0).abs() < 0.01, "velocity was {}", velocity);
+    }
+
+    #[test]
+    fn box_settles_on_the_floor() {
+        let mut app = app();
+        spawn_floor(&mut app);
+        let block = Collider::cuboid_from_half_extents(Vec3::ONE);
+        let body = RigidBody::new(1.0, &block).with_velocity(Vec3::new(3.0, 0.0, 0.0)).with_friction(1.0);
+        let block = spawn_body(&mut app, block, Vec3::new(0.0, 2.0, 0.0), body);
+
+        for _ in 0..120 {
+            app.world.run_schedule(FixedUpdate);
+        }
+
+        //friction should have stopped it sliding and the four corners should keep it flat and out of the floor
+        let body = app.world.get::<RigidBody>(block).unwrap();
+        assert!(body.linear_velocity.length() < 0.05, "velocity was {}", body.linear_velocity);
+        assert!(body.angular_velocity.length() < 0.05, "spin was {}", body.angular_velocity);
+        let transform = app.world.get::<Transform>(block).unwrap();
+        assert!((transform.translation.y - 2.0).abs() < 0.05, "block ended up at {}", transform.translation);
+        assert!(transform.rotation.angle_between(Quat::IDENTITY) < 0.01, "block tipped over to {}", transform.rotation);
+    }
+
+    #[test]
+    fn impulse_off_centre_spins() {
+        let block = Collider::cuboid_from_half_extents(Vec3::ONE);
+        let mut body = RigidBody::new(2.0, &block);
+        body.apply_impulse(Vec3::new(0.0, 0.0, 2.0), Vec3::new(1.0, 0.0, 0.0), &GlobalTransform::IDENTITY);
+
+        assert_eq!(body.linear_velocity, Vec3::new(0.0, 0.0, 1.0));
+        //pushing the +x side forward turns it clockwise looking down
+        assert!(body.angular_velocity.y < 0.0 && body.angular_velocity.x == 0.0, "spin was {}", body.angular_velocity);
+    }
+    #[test]
+    fn massless_bodies_ignore_impulses() {
+        let mut body = RigidBody::new(0.0, &Collider::cuboid_from_half_extents(Vec3::ONE));
+        body.apply_impulse(Vec3::new(0.0, 0.0, 2.0), Vec3::new(1.0, 0.0, 0.0), &GlobalTransform::IDENTITY);
+
+        assert_eq!(body.linear_velocity, Vec3::ZERO);
+        assert_eq!(body.angular_velocity, Vec3::ZERO);
+    }
+
+    #[test]
+    fn scaled_sphere_inertia() {
+        let mut ball = Collider::sphere_from_radius(1.0);
+        ball.set_transform(&GlobalTransform::from_scale(Vec3::splat(2.0)));
+
+        assert!(ball.inertia(1.0).abs_diff_eq(Vec3::splat(0.4 * 4.0), 0.0001), "inertia was {}", ball.inertia(1.0));
+    }
+}
@@ -0,0 +1,270 @@
+use bevy::prelude::*;
+
+use super::{gjk_iterations, half_space_contact, support, Collider, CollisionDiagnostics, GjkResult, Shapes, SupportPoint};
+
+//how close the new support point has to be to the closest face before we stop expanding
+pub const EPA_TOLERANCE: f32 = 0.0001;
+//stops curved shapes (spheres) from expanding forever since they never give an exact face
+pub const EPA_MAX_ITERATIONS: u32 = 64;
+
+/**
+ * Result of the expanding polytope algorithm.
+ * normal points from s1 towards s2, so moving s1 by -normal * depth (or s2 by normal * depth) separates them.
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct Contact {
+    pub depth: f32,
+    pub normal: Vec3,
+    pub point_on_s1: Vec3,
+    pub point_on_s2: Vec3,
+    //which part of a compound collider was hit, always 0 for a single shape
+    pub part_s1: usize,
+    pub part_s2: usize,
+}
+
+impl Contact {
+    //the same contact seen from s2's side
+    pub fn flipped(&self) -> Self {
+        return Self {
+            depth: self.depth,
+            normal: -self.normal,
+            point_on_s1: self.point_on_s2,
+            point_on_s2: self.point_on_s1,
+            part_s1: self.part_s2,
+            part_s2: self.part_s1,
+        };
+    }
+}
+
+//a triangle on the polytope, the indices point into the polytope's vertex list
+//normal always faces away from the origin and distance is how far the face's plane is from it
+struct Face {
+    indices: [usize; 3],
+    normal: Vec3,
+    distance: f32,
+}
+
+impl Face {
+    fn new(polytope: &Vec<SupportPoint>, a: usize, b: usize, c: usize) -> Self {
+        let (pa, pb, pc) = (polytope[a].point, polytope[b].point, polytope[c].point);
+        let mut normal = (pb - pa).cross(pc - pa).normalize_or_zero();
+        let mut distance = normal.dot(pa);
+        let mut indices = [a, b, c];
+
+        //the origin is inside the polytope so a face pointing towards it has the wrong winding
+        if distance < 0.0 {
+            normal = -normal;
+            distance = -distance;
+            indices.swap(1, 2);
+        }
+
+        return Self { indices, normal, distance };
+    }
+}
+
+/**
+ * Runs gjk and then epa on the result.
+ * Returns None if the objects are not touching.
+ */
+pub fn contact (
+    s1: &Collider,
+    s2: &Collider,
+) -> Option<Contact> {
+    return contact_profiled(s1, s2, &mut CollisionDiagnostics::default());
+}
+
+//contact that also counts the gjk and epa iterations into diagnostics
+pub(super) fn contact_profiled (
+    s1: &Collider,
+    s2: &Collider,
+    diagnostics: &mut CollisionDiagnostics,
+) -> Option<Contact> {
+    //half-spaces have no furthest point for gjk to use but the contact is easy to work out directly
+    match (&s1.shape, &s2.shape) {
+        (Shapes::HalfSpace, Shapes::HalfSpace) => return None,
+        (Shapes::HalfSpace, _) => return half_space_contact(s1, s2),
+        (_, Shapes::HalfSpace) => return half_space_contact(s2, s1).map(|contact| contact.flipped()),
+        _ => {},
+    }
+
+    let (result, iterations) = gjk_iterations(s1, s2);
+    diagnostics.record_gjk(iterations);
+    let GjkResult::Intersecting(simplex) = result else {
+        return None;
+    };
+    let (contact, iterations) = epa_iterations(s1, s2, simplex);
+    diagnostics.epa_iterations += iterations as usize;
+    return contact;
+}
+
+/**
+ * Expands the tetrahedron returned by gjk until it finds the face of the minkowski difference closest to the origin.
+ * The distance to that face is the penetration depth and its normal is the contact normal.
+ */
+pub fn epa (
+    s1: &Collider,
+    s2: &Collider,
+    simplex: Vec<SupportPoint>,
+) -> Option<Contact> {
+    return epa_iterations(s1, s2, simplex).0;
+}
+
+//epa that also says how many iterations it took
+fn epa_iterations (
+    s1: &Collider,
+    s2: &Collider,
+    simplex: Vec<SupportPoint>,
+) -> (Option<Contact>, u32) {
+    if simplex.len() != 4 {
+        return (None, 0);
+    }
+
+    let mut polytope = simplex;
+    let mut faces = vec![
+        Face::new(&polytope, 0, 1, 2),
+        Face::new(&polytope, 0, 3, 1),
+        Face::new(&polytope, 0, 2, 3),
+        Face::new(&polytope, 1, 3, 2),
+    ];
+
+    for iteration in 1..=EPA_MAX_ITERATIONS {
+        let Some(closest) = closest_face(&faces) else {
+            return (None, iteration);
+        };
+        let (normal, distance) = (faces[closest].normal, faces[closest].distance);
+
+        let new_point = support(s1, s2, &normal);
+
+        //the polytope can't be pushed out any further in this direction so we have found the boundary
+        if new_point.point.dot(normal) - distance < EPA_TOLERANCE {
+            return (Some(build_contact(&polytope, &faces[closest])), iteration);
+        }
+
+        //every face the new point can see has to be removed
+        //the edges that only belong to one removed face make up the hole we need to fill
+        let mut horizon: Vec<(usize, usize)> = Vec::new();
+        let mut i = 0;
+        while i < faces.len() {
+            let face = &faces[i];
+            if face.normal.dot(new_point.point - polytope[face.indices[0]].point) > 0.0 {
+                let [a, b, c] = face.indices;
+                add_unique_edge(&mut horizon, a, b);
+                add_unique_edge(&mut horizon, b, c);
+                add_unique_edge(&mut horizon, c, a);
+                faces.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+
+        polytope.push(new_point);
+        let new_index = polytope.len() - 1;
+        for (a, b) in horizon {
+            faces.push(Face::new(&polytope, a, b, new_index));
+        }
+    }
+
+    //ran out of iterations, the closest face we have is still a decent estimate
+    let contact = closest_face(&faces).map(|closest| build_contact(&polytope, &faces[closest]));
+    return (contact, EPA_MAX_ITERATIONS);
+}
+
+fn closest_face(faces: &Vec<Face>) -> Option<usize> {
+    let mut min_distance = f32::INFINITY;
+    let mut min_index = None;
+    for (i, face) in faces.iter().enumerate() {
+        //degenerate faces have no normal and would always look closest
+        if face.normal == Vec3::ZERO {
+            continue;
+        }
+        if face.distance < min_distance {
+            min_distance = face.distance;
+            min_index = Some(i);
+        }
+    }
+    return min_index;
+}
+
+//if the reversed edge is already there then both faces sharing it were removed so it isn't part of the horizon
+fn add_unique_edge(edges: &mut Vec<(usize, usize)>, a: usize, b: usize) {
+    if let Some(reverse) = edges.iter().position(|&edge| edge == (b, a)) {
+        edges.swap_remove(reverse);
+    } else {
+        edges.push((a, b));
+    }
+}
+
+fn build_contact(polytope: &Vec<SupportPoint>, face: &Face) -> Contact {
+    let [a, b, c] = face.indices;
+    let (sa, sb, sc) = (polytope[a], polytope[b], polytope[c]);
+
+    //the closest point on the minkowski difference to the origin, written in terms of the face's corners
+    //the same weights applied to the points each corner came from give the contact point on each collider
+    let closest = face.normal * face.distance;
+    let weights = barycentric(closest, sa.point, sb.point, sc.point);
+
+    return Contact {
+        depth: face.distance,
+        normal: face.normal,
+        point_on_s1: sa.on_s1 * weights.x + sb.on_s1 * weights.y + sc.on_s1 * weights.z,
+        point_on_s2: sa.on_s2 * weights.x + sb.on_s2 * weights.y + sc.on_s2 * weights.z,
+        part_s1: 0,
+        part_s2: 0,
+    };
+}
+
+//barycentric coordinates of p projected onto the triangle abc
+pub(crate) fn barycentric(p: Vec3, a: Vec3, b: Vec3, c: Vec3) -> Vec3 {
+    let (v0, v1, v2) = (b - a, c - a, p - a);
+    let (d00, d01, d11) = (v0.dot(v0), v0.dot(v1), v1.dot(v1));
+    let (d20, d21) = (v2.dot(v0), v2.dot(v1));
+    let denom = d00 * d11 - d01 * d01;
+
+    //a sliver triangle has no sensible weights so just use the first corner
+    if denom.abs() < f32::EPSILON {
+        return Vec3::new(1.0, 0.0, 0.0);
+    }
+
+    let v = (d11 * d20 - d01 * d21) / denom;
+    let w = (d00 * d21 - d01 * d20) / denom;
+    return Vec3::new(1.0 - v - w, v, w);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::fixtures::{cube_at, sphere_at};
+
+    #[test]
+    fn cube_cube_penetration() {
+        let cube1 = cube_at(Vec3::ZERO);
+        let cube2 = cube_at(Vec3::new(1.5, 0.0, 0.0));
+
+        let result = contact(&cube1, &cube2).expect("cubes should overlap");
+        assert!((result.depth - 0.5).abs() < 0.001, "depth was {}", result.depth);
+        assert!(result.normal.abs_diff_eq(Vec3::X, 0.001), "normal was {}", result.normal);
+        //both contact points should sit on the overlapping faces
+        assert!((result.point_on_s1.x - 1.0).abs() < 0.001, "s1 point was {}", result.point_on_s1);
+        assert!((result.point_on_s2.x - 0.5).abs() < 0.001, "s2 point was {}", result.point_on_s2);
+    }
+
+    #[test]
+    fn sphere_sphere_penetration() {
+        let sphere1 = sphere_at(1.0, Vec3::ZERO);
+        let sphere2 = sphere_at(1.0, Vec3::new(0.0, 1.5, 0.0));
+
+        let result = contact(&sphere1, &sphere2).expect("spheres should overlap");
+        assert!((result.depth - 0.5).abs() < 0.01, "depth was {}", result.depth);
+        assert!(result.normal.abs_diff_eq(Vec3::Y, 0.01), "normal was {}", result.normal);
+        assert!(result.point_on_s1.abs_diff_eq(Vec3::new(0.0, 1.0, 0.0), 0.01), "s1 point was {}", result.point_on_s1);
+        assert!(result.point_on_s2.abs_diff_eq(Vec3::new(0.0, 0.5, 0.0), 0.01), "s2 point was {}", result.point_on_s2);
+    }
+
+    #[test]
+    fn separated_has_no_contact() {
+        let cube1 = cube_at(Vec3::ZERO);
+        let cube2 = cube_at(Vec3::new(3.0, 0.0, 0.0));
+
+        assert!(contact(&cube1, &cube2).is_none());
+    }
+}
@@ -0,0 +1,29 @@
+use bevy::prelude::*;
+
+use super::Collider;
+
+//the corners of a cube with half extents of 1
+pub(super) const CUBE_POINTS: &[Vec3] = &[
+        Vec3::new(1.0,1.0,1.0),
+        Vec3::new(1.0,1.0,-1.0),
+        Vec3::new(1.0,-1.0,1.0),
+        Vec3::new(1.0,-1.0,-1.0),
+        Vec3::new(-1.0,1.0,1.0),
+        Vec3::new(-1.0,1.0,-1.0),
+        Vec3::new(-1.0,-1.0,1.0),
+        Vec3::new(-1.0,-1.0,-1.0),
+    ];
+
+//moves a collider the same way apply_transform_collider would for an entity at translation
+pub(super) fn at(mut collider: Collider, translation: Vec3) -> Collider {
+    collider.set_transform(&GlobalTransform::from_translation(translation));
+    return collider;
+}
+
+pub(super) fn cube_at(translation: Vec3) -> Collider {
+    return at(Collider::poly_from_points(CUBE_POINTS.to_vec()), translation);
+}
+
+pub(super) fn sphere_at(radius: f32, translation: Vec3) -> Collider {
+    return at(Collider::sphere_from_radius(radius), translation);
+}
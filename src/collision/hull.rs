@@ -0,0 +1,317 @@
+use bevy::prelude::*;
+use bevy::render::mesh::VertexAttributeValues;
+
+use super::Collider;
+
+/**
+ * Put this on an entity with a Handle<Mesh> to have a Collider built from the mesh's convex hull.
+ * The Collider is added by collider_from_mesh once the mesh asset has loaded.
+ */
+#[derive(Component, Default)]
+pub struct ConvexHullFromMesh;
+
+impl Collider {
+    //builds a polyhedron out of the convex hull of the mesh's vertex positions
+    pub fn poly_from_mesh(mesh: &Mesh) -> Option<Self> {
+        let Some(VertexAttributeValues::Float32x3(positions)) = mesh.attribute(Mesh::ATTRIBUTE_POSITION) else {
+            return None;
+        };
+        let points: Vec<Vec3> = positions.iter().map(|position| Vec3::from_array(*position)).collect();
+        if points.is_empty() {
+            return None;
+        }
+
+        return Some(Self::poly_from_points(convex_hull(&points)));
+    }
+
+    //returns None while the mesh is still loading
+    pub fn poly_from_mesh_handle(handle: &Handle<Mesh>, meshes: &Assets<Mesh>) -> Option<Self> {
+        return Self::poly_from_mesh(meshes.get(handle)?);
+    }
+}
+
+pub fn collider_from_mesh (
+    mut commands: Commands,
+    q_waiting: Query<(Entity, &Handle<Mesh>), (With<ConvexHullFromMesh>, Without<Collider>)>,
+    meshes: Option<Res<Assets<Mesh>>>,
+) {
+    //apps without rendering don't have any meshes to build from
+    let Some(meshes) = meshes else {
+        return;
+    };
+
+    for (entity, handle) in q_waiting.iter() {
+        if let Some(collider) = Collider::poly_from_mesh_handle(handle, &meshes) {
+            commands.entity(entity).insert(collider).remove::<ConvexHullFromMesh>();
+        }
+    }
+}
+
+//a triangle on the hull, normal faces outwards and offset is the plane's distance from the origin
+struct HullFace {
+    vertices: [usize; 3],
+    normal: Vec3,
+    offset: f32,
+    //points that are still outside this face
+    outside: Vec<usize>,
+    removed: bool,
+}
+
+impl HullFace {
+    fn new(points: &[Vec3], a: usize, b: usize, c: usize, interior: Vec3) -> Self {
+        let mut vertices = [a, b, c];
+        let mut normal = (points[b] - points[a]).cross(points[c] - points[a]).normalize_or_zero();
+        //every face has to point away from a point we know is inside the hull
+        if normal.dot(interior - points[a]) > 0.0 {
+            normal = -normal;
+            vertices.swap(1, 2);
+        }
+        return Self {
+            vertices,
+            normal,
+            offset: normal.dot(points[a]),
+            outside: Vec::new(),
+            removed: false,
+        };
+    }
+
+    fn distance(&self, point: Vec3) -> f32 {
+        return self.normal.dot(point) - self.offset;
+    }
+}
+
+/**
+ * Quickhull, returns only the points that are corners of the convex hull.
+ * If the points are all on a plane or a line there is no volume to build a hull from so they are handed back as they are.
+ */
+pub fn convex_hull(points: &[Vec3]) -> Vec<Vec3> {
+    let Some(faces) = hull_faces(points) else {
+        return points.to_vec();
+    };
+
+    let mut corners: Vec<usize> = faces.iter().flat_map(|face| face.vertices).collect();
+    corners.sort();
+    corners.dedup();
+    return corners.iter().map(|&i| points[i]).collect();
+}
+
+/**
+ * The edges of the convex hull as pairs of indices into points, used to draw outlines of polyhedra.
+ * Edges between two triangles on the same flat face are left out so a cube comes back with its 12 edges and no diagonals.
+ * Flat point sets have no faces so every point is joined to every other one.
+ */
+pub fn convex_hull_edges(points: &[Vec3]) -> Vec<(usize, usize)> {
+    let Some(faces) = hull_faces(points) else {
+        return (0..points.len()).flat_map(|i| (i + 1..points.len()).map(move |j| (i, j))).collect();
+    };
+
+    let mut edges = Vec::new();
+    for face in &faces {
+        let [a, b, c] = face.vertices;
+        for (from, to) in [(a, b), (b, c), (c, a)] {
+            //every edge is on two faces going opposite ways round, only keep it from one of them
+            if from > to {
+                continue;
+            }
+            let neighbour = faces.iter().find(|other| {
+                let [a, b, c] = other.vertices;
+                return [(a, b), (b, c), (c, a)].contains(&(to, from));
+            });
+            if neighbour.map_or(true, |neighbour| neighbour.normal.dot(face.normal) < 1.0 - 1.0e-4) {
+                edges.push((from, to));
+            }
+        }
+    }
+    return edges;
+}
+
+//runs quickhull and returns the triangles left on the hull, None if the points have no volume
+fn hull_faces(points: &[Vec3]) -> Option<Vec<HullFace>> {
+    let initial = initial_tetrahedron(points)?;
+
+    //tolerance scales with the size of the mesh so big and small meshes both work
+    let (min, max) = points.iter().fold((Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)), |(min, max), point| (min.min(*point), max.max(*point)));
+    let epsilon = (max - min).max_element() * 1.0e-5;
+
+    let [a, b, c, d] = initial;
+    let interior = (points[a] + points[b] + points[c] + points[d]) / 4.0;
+    let mut faces = vec![
+        HullFace::new(points, a, b, c, interior),
+        HullFace::new(points, a, b, d, interior),
+        HullFace::new(points, a, c, d, interior),
+        HullFace::new(points, b, c, d, interior),
+    ];
+
+    //every point goes into the outside list of the first face it is in front of, points behind every face are inside already
+    let all_points: Vec<usize> = (0..points.len()).filter(|i| !initial.contains(i)).collect();
+    assign_outside(points, &mut faces, 0, &all_points, epsilon);
+
+    while let Some(face_index) = faces.iter().position(|face| !face.removed && !face.outside.is_empty()) {
+        //the point furthest out from the face has to be a corner of the hull
+        let face = &faces[face_index];
+        let eye = *face.outside.iter().max_by(|&&i, &&j| face.distance(points[i]).total_cmp(&face.distance(points[j]))).unwrap();
+
+        //remove every face that can see the new corner, their edges that aren't shared with each other form the horizon
+        let mut horizon: Vec<(usize, usize)> = Vec::new();
+        let mut orphans: Vec<usize> = Vec::new();
+        for face in faces.iter_mut().filter(|face| !face.removed) {
+            if face.distance(points[eye]) > epsilon {
+                let [a, b, c] = face.vertices;
+                for (from, to) in [(a, b), (b, c), (c, a)] {
+                    if let Some(reverse) = horizon.iter().position(|&edge| edge == (to, from)) {
+                        horizon.swap_remove(reverse);
+                    } else {
+                        horizon.push((from, to));
+                    }
+                }
+                face.removed = true;
+                orphans.append(&mut face.outside);
+            }
+        }
+
+        //fill the hole with a fan of faces from the horizon to the new corner
+        let first_new = faces.len();
+        for (from, to) in horizon {
+            faces.push(HullFace::new(points, from, to, eye, interior));
+        }
+        orphans.retain(|&i| i != eye);
+        assign_outside(points, &mut faces, first_new, &orphans, epsilon);
+    }
+
+    faces.retain(|face| !face.removed);
+    return Some(faces);
+}
+
+fn assign_outside(points: &[Vec3], faces: &mut Vec<HullFace>, first_face: usize, candidates: &[usize], epsilon: f32) {
+    for &i in candidates {
+        for face in faces[first_face..].iter_mut().filter(|face| !face.removed) {
+            if face.distance(points[i]) > epsilon {
+                face.outside.push(i);
+                break;
+            }
+        }
+    }
+}
+
+//picks four points that are as spread out as possible so the starting hull has some volume
+fn initial_tetrahedron(points: &[Vec3]) -> Option<[usize; 4]> {
+    if points.len() < 4 {
+        return None;
+    }
+
+    //the two points furthest apart along any axis
+    let mut best = (0, 0, 0.0);
+    for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+        let min = furthest(points, |point| -point.dot(axis));
+        let max = furthest(points, |point| point.dot(axis));
+        let length = points[max].dot(axis) - points[min].dot(axis);
+        if length > best.2 {
+            best = (min, max, length);
+        }
+    }
+    let (a, b, length) = best;
+    if length <= f32::EPSILON {
+        return None;
+    }
+    let epsilon = length * 1.0e-5;
+
+    //the point furthest from the line ab
+    let ab = (points[b] - points[a]).normalize();
+    let c = furthest(points, |point| (point - points[a]).cross(ab).length());
+    if (points[c] - points[a]).cross(ab).length() <= epsilon {
+        return None;
+    }
+
+    //the point furthest from the plane abc
+    let normal = (points[b] - points[a]).cross(points[c] - points[a]).normalize();
+    let d = furthest(points, |point| normal.dot(point - points[a]).abs());
+    if normal.dot(points[d] - points[a]).abs() <= epsilon {
+        return None;
+    }
+
+    return Some([a, b, c, d]);
+}
+
+//index of the point with the biggest measure
+//ties are broken in a fixed direction so the middle of an edge never gets picked over its ends, otherwise it would end up as a corner
+fn furthest(points: &[Vec3], measure: impl Fn(Vec3) -> f32) -> usize {
+    let max = points.iter().map(|point| measure(*point)).fold(f32::MIN, f32::max);
+    let tolerance = max.abs().max(1.0) * 1.0e-6;
+    let tie_break = Vec3::new(1.0, 1.0e-3, 1.0e-6);
+
+    return (0..points.len())
+        .filter(|&i| measure(points[i]) >= max - tolerance)
+        .max_by(|&i, &j| points[i].dot(tie_break).total_cmp(&points[j].dot(tie_break)))
+        .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hull_drops_inside_points() {
+        let mut points = vec![Vec3::ZERO, Vec3::new(0.5, -0.2, 0.1), Vec3::new(-0.9, 0.9, 0.9)];
+        for x in [-1.0, 1.0] {
+            for y in [-1.0, 1.0] {
+                for z in [-1.0, 1.0] {
+                    points.push(Vec3::new(x, y, z));
+                }
+            }
+        }
+        //a point halfway along an edge isn't a corner either
+        points.push(Vec3::new(1.0, 1.0, 0.0));
+
+        let hull = convex_hull(&points);
+        assert_eq!(hull.len(), 8, "hull was {:?}", hull);
+        assert!(hull.iter().all(|point| point.abs() == Vec3::ONE));
+    }
+
+    #[test]
+    fn hull_from_cube_mesh() {
+        //bevy's cube mesh has 24 vertices since each face has its own normals
+        let mesh = Mesh::from(shape::Cube { size: 10.0 });
+        let collider = Collider::poly_from_mesh(&mesh).unwrap();
+
+        assert_eq!(collider.local_points.len(), 8);
+        assert_eq!(collider.support(Vec3::ONE.normalize()), Vec3::splat(5.0));
+    }
+
+    #[test]
+    fn hull_from_sphere_mesh_stays_convex() {
+        let mesh = Mesh::from(shape::UVSphere { radius: 2.0, ..default() });
+        let collider = Collider::poly_from_mesh(&mesh).unwrap();
+
+        //every direction should find a point right on the surface
+        for d in [Vec3::X, Vec3::NEG_Y, Vec3::new(1.0, 1.0, -1.0).normalize()] {
+            let point = collider.support(d);
+            assert!(point.length() <= 2.0 + 0.001 && point.dot(d) > 1.9, "support returned {}", point);
+        }
+    }
+
+    #[test]
+    fn hull_edges_skip_face_diagonals() {
+        let mut points = Vec::new();
+        for x in [-1.0, 1.0] {
+            for y in [-1.0, 1.0] {
+                for z in [-1.0, 1.0] {
+                    points.push(Vec3::new(x, y, z));
+                }
+            }
+        }
+
+        //every edge of a cube runs along one axis, a diagonal would change two coordinates
+        let edges = convex_hull_edges(&points);
+        assert_eq!(edges.len(), 12, "edges were {:?}", edges);
+        for (a, b) in edges {
+            let changed = (points[a] - points[b]).abs();
+            assert_eq!(changed.x + changed.y + changed.z, 2.0, "{} to {} isn't a cube edge", points[a], points[b]);
+        }
+    }
+
+    #[test]
+    fn flat_points_are_returned_unchanged() {
+        let points = vec![Vec3::ZERO, Vec3::X, Vec3::Z, Vec3::ONE - Vec3::Y];
+        assert_eq!(convex_hull(&points), points);
+    }
+}
@@ -0,0 +1,107 @@
+use bevy::prelude::*;
+
+/**
+ * Smooths out the movement of an entity moved by the physics systems in FixedUpdate.
+ * Physics ticks don't line up with frames, so without this things jump forward on frames with an extra tick and stop on frames with none.
+ * During Update the Transform is the latest physics state like normal, then it is drawn part of the way between the last two ticks.
+ * Setting the Transform in Update teleports the entity there without smoothing.
+ */
+#[derive(Component, Default, Clone, Copy, Debug)]
+pub struct Interpolated {
+    //the physics state at the start and end of the last tick, None until the first tick after spawning
+    previous: Option<Transform>,
+    current: Option<Transform>,
+}
+
+//puts back the real physics state over the one drawn last frame before anything gets to read it
+pub fn restore_physics_transforms (
+    mut q_interpolated: Query<(&Interpolated, &mut Transform)>,
+) {
+    for (interpolated, mut transform) in q_interpolated.iter_mut() {
+        if let Some(current) = interpolated.current {
+            *transform = current;
+        }
+    }
+}
+
+pub fn record_previous_transforms (
+    mut q_interpolated: Query<(&mut Interpolated, &Transform)>,
+) {
+    for (mut interpolated, transform) in q_interpolated.iter_mut() {
+        interpolated.previous = Some(*transform);
+    }
+}
+
+pub fn record_current_transforms (
+    mut q_interpolated: Query<(&mut Interpolated, &Transform)>,
+) {
+    for (mut interpolated, transform) in q_interpolated.iter_mut() {
+        interpolated.current = Some(*transform);
+    }
+}
+
+//the time left over after the last tick says how far through the next one the frame is drawn
+pub fn interpolate_transforms (
+    fixed_time: Res<FixedTime>,
+    mut q_interpolated: Query<(&mut Interpolated, &mut Transform)>,
+) {
+    let fraction = (fixed_time.accumulated().as_secs_f32() / fixed_time.period.as_secs_f32()).clamp(0.0, 1.0);
+    for (mut interpolated, mut transform) in q_interpolated.iter_mut() {
+        let (Some(previous), Some(current)) = (interpolated.previous, interpolated.current) else {
+            continue;
+        };
+        //something outside of physics moved it this frame
+        if *transform != current {
+            interpolated.previous = Some(*transform);
+            interpolated.current = Some(*transform);
+            continue;
+        }
+        transform.translation = previous.translation.lerp(current.translation, fraction);
+        transform.rotation = previous.rotation.slerp(current.rotation, fraction);
+        transform.scale = previous.scale.lerp(current.scale, fraction);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::collision::{BodyType, Collider, CollisionPlugin, RigidBody};
+
+    #[test]
+    fn drawn_between_ticks() {
+        let mut app = App::new();
+        app.add_plugins(CollisionPlugin);
+        let body = RigidBody::new(1.0, &Collider::sphere_from_radius(1.0)).with_gravity_scale(0.0);
+        let speed = 1.0 / app.world.resource::<FixedTime>().period.as_secs_f32();
+        let ball = app.world.spawn((
+            body.with_velocity(Vec3::new(speed, 0.0, 0.0)),
+            BodyType::Dynamic,
+            Transform::IDENTITY,
+            Interpolated::default(),
+        )).id();
+
+        //one tick moves the ball along by 1
+        app.world.run_schedule(FixedUpdate);
+
+        //a quarter of the way to the next tick the ball should be a quarter of the way along
+        let mut fixed_time = app.world.resource_mut::<FixedTime>();
+        let quarter = fixed_time.period / 4;
+        fixed_time.tick(quarter);
+        app.world.run_schedule(PostUpdate);
+        let x = app.world.get::<Transform>(ball).unwrap().translation.x;
+        assert!((x - 0.25).abs() < 0.001, "ball drawn at {}", x);
+
+        //the physics state comes back before the next frame
+        app.world.run_schedule(PreUpdate);
+        let x = app.world.get::<Transform>(ball).unwrap().translation.x;
+        assert!((x - 1.0).abs() < 0.001, "ball restored to {}", x);
+
+        //teleporting skips the smoothing
+        app.world.get_mut::<Transform>(ball).unwrap().translation.x = 10.0;
+        app.world.resource_mut::<FixedTime>().tick(Duration::from_millis(1));
+        app.world.run_schedule(PostUpdate);
+        assert_eq!(app.world.get::<Transform>(ball).unwrap().translation.x, 10.0);
+    }
+}
@@ -0,0 +1,321 @@
+use bevy::prelude::*;
+
+use super::{Collider, Contact, Shapes};
+
+//corners within this distance of the furthest one along the normal count as part of the same face
+pub const MANIFOLD_TOLERANCE: f32 = 0.02;
+//four points are enough to hold any face flat, more just slows the solver down
+pub const MANIFOLD_MAX_POINTS: usize = 4;
+//a point from last frame this close to a new one is taken to be the same point and hands over its impulses
+pub const MANIFOLD_MATCH_DISTANCE: f32 = 0.1;
+
+/**
+ * One point of a contact manifold.
+ * normal_impulse and tangent_impulse are what the solver pushed with at this point, they get carried over to next frame
+ * when the point is still there so the solver can start from last frame's answer instead of from nothing.
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct ManifoldPoint {
+    pub point_on_s1: Vec3,
+    pub point_on_s2: Vec3,
+    //0 for points that are only just touching
+    pub depth: f32,
+    pub normal_impulse: f32,
+    pub tangent_impulse: Vec3,
+}
+
+/**
+ * Every point where two colliders touch, all sharing the same normal.
+ * A box lying on the floor gets a point under each corner instead of just the single deepest one from epa,
+ * which is what stops it rocking back and forth. normal points from s1 towards s2 like Contact.
+ */
+#[derive(Clone, Debug)]
+pub struct ContactManifold {
+    pub normal: Vec3,
+    pub points: Vec<ManifoldPoint>,
+}
+
+impl From<Contact> for ContactManifold {
+    fn from(contact: Contact) -> Self {
+        return Self {
+            normal: contact.normal,
+            points: vec![ManifoldPoint {
+                point_on_s1: contact.point_on_s1,
+                point_on_s2: contact.point_on_s2,
+                depth: contact.depth,
+                normal_impulse: 0.0,
+                tangent_impulse: Vec3::ZERO,
+            }],
+        };
+    }
+}
+
+impl ContactManifold {
+    //the same manifold seen from s2's side
+    pub fn flipped(&self) -> Self {
+        return Self {
+            normal: -self.normal,
+            points: self.points.iter().map(|point| ManifoldPoint {
+                point_on_s1: point.point_on_s2,
+                point_on_s2: point.point_on_s1,
+                ..*point
+            }).collect(),
+        };
+    }
+
+    //copies the impulses over from whichever of last frame's points each point lines up with
+    pub fn warm_start(&mut self, previous: &ContactManifold) {
+        for point in self.points.iter_mut() {
+            let closest = previous.points.iter()
+                .map(|old| (old, old.point_on_s1.distance(point.point_on_s1)))
+                .filter(|(_, distance)| *distance < MANIFOLD_MATCH_DISTANCE)
+                .min_by(|(_, a), (_, b)| a.total_cmp(b));
+            if let Some((old, _)) = closest {
+                point.normal_impulse = old.normal_impulse;
+                point.tangent_impulse = old.tangent_impulse;
+            }
+        }
+    }
+}
+
+/**
+ * Builds the manifold for two convex shapes that epa says are touching.
+ * Polyhedra and cuboids get their touching faces clipped against each other and anything against a half-space
+ * gets every corner under the surface. Round shapes only ever touch at one point so they just use the contact.
+ */
+pub fn contact_manifold(s1: &Collider, s2: &Collider, contact: &Contact) -> ContactManifold {
+    let manifold = match (&s1.shape, &s2.shape) {
+        (Shapes::HalfSpace, _) => half_space_manifold(s1, s2),
+        (_, Shapes::HalfSpace) => half_space_manifold(s2, s1).map(|manifold| manifold.flipped()),
+        _ => clipped_manifold(s1, s2, contact.normal),
+    };
+    return manifold.unwrap_or_else(|| ContactManifold::from(*contact));
+}
+
+impl Collider {
+    //the corners of shapes with flat faces, None for round ones
+    fn vertices(&self) -> Option<Vec<Vec3>> {
+        match self.shape {
+            Shapes::Polyhedron => {
+                return Some(self.transformed_points.clone());
+            },
+            Shapes::Cuboid => {
+                let centre = self.transformed_points[0];
+                let (x, y, z) = (self.transformed_points[1] - centre, self.transformed_points[2] - centre, self.transformed_points[3] - centre);
+                let mut corners = Vec::new();
+                for sx in [-1.0, 1.0] {
+                    for sy in [-1.0, 1.0] {
+                        for sz in [-1.0, 1.0] {
+                            corners.push(centre + x * sx + y * sy + z * sz);
+                        }
+                    }
+                }
+                return Some(corners);
+            },
+            _ => {
+                return None;
+            }
+        }
+    }
+}
+
+fn half_space_manifold(plane: &Collider, other: &Collider) -> Option<ContactManifold> {
+    let vertices = other.vertices()?;
+    let origin = plane.transformed_points[0];
+    let normal = (plane.transformed_points[1] - origin).normalize_or_zero();
+
+    let points: Vec<ManifoldPoint> = vertices.iter().filter_map(|&vertex| {
+        let height = normal.dot(vertex - origin);
+        if height > MANIFOLD_TOLERANCE {
+            return None;
+        }
+        return Some(ManifoldPoint {
+            point_on_s1: vertex - normal * height,
+            point_on_s2: vertex,
+            depth: (-height).max(0.0),
+            normal_impulse: 0.0,
+            tangent_impulse: Vec3::ZERO,
+        });
+    }).collect();
+
+    if points.is_empty() {
+        return None;
+    }
+    return Some(ContactManifold { normal, points: reduce(points) });
+}
+
+//clips the face of one shape that faces the other against the sides of the other shape's face
+fn clipped_manifold(s1: &Collider, s2: &Collider, normal: Vec3) -> Option<ContactManifold> {
+    let (face1, face2) = (support_face(&s1.vertices()?, normal), support_face(&s2.vertices()?, -normal));
+
+    //the bigger face is the one that gets clipped against, s1's face if they're the same
+    let (reference, incident, reference_is_s1) = if face1.len() >= 3 && face1.len() >= face2.len() {
+        (face1, face2, true)
+    } else if face2.len() >= 3 {
+        (face2, face1, false)
+    } else {
+        //two edges or corners touching only have one point anyway
+        return None;
+    };
+    let reference_normal = if reference_is_s1 { normal } else { -normal };
+    let centre = reference.iter().sum::<Vec3>() / reference.len() as f32;
+
+    //only keep the part of the incident face that sits inside every side of the reference face
+    let mut clipped = incident;
+    for (i, &a) in reference.iter().enumerate() {
+        let b = reference[(i + 1) % reference.len()];
+        let mut inward = reference_normal.cross(b - a);
+        if inward.dot(centre - a) < 0.0 {
+            inward = -inward;
+        }
+        clipped = clip(&clipped, a, inward);
+    }
+
+    let points: Vec<ManifoldPoint> = clipped.iter().filter_map(|&point| {
+        //how far the point is underneath the reference face
+        let depth = reference_normal.dot(reference[0] - point);
+        if depth < -MANIFOLD_TOLERANCE {
+            return None;
+        }
+        let on_reference = point + reference_normal * depth;
+        let (point_on_s1, point_on_s2) = if reference_is_s1 { (on_reference, point) } else { (point, on_reference) };
+        return Some(ManifoldPoint {
+            point_on_s1,
+            point_on_s2,
+            depth: depth.max(0.0),
+            normal_impulse: 0.0,
+            tangent_impulse: Vec3::ZERO,
+        });
+    }).collect();
+
+    if points.is_empty() {
+        return None;
+    }
+    return Some(ContactManifold { normal, points: reduce(points) });
+}
+
+//every vertex that's as far along d as the furthest one, sorted into order around the face
+fn support_face(vertices: &[Vec3], d: Vec3) -> Vec<Vec3> {
+    let furthest = vertices.iter().map(|vertex| vertex.dot(d)).fold(f32::MIN, f32::max);
+    let mut face: Vec<Vec3> = vertices.iter().copied().filter(|vertex| vertex.dot(d) >= furthest - MANIFOLD_TOLERANCE).collect();
+
+    let centre = face.iter().sum::<Vec3>() / face.len() as f32;
+    let (u, v) = d.any_orthonormal_pair();
+    let angle = |point: &Vec3| (*point - centre).dot(v).atan2((*point - centre).dot(u));
+    face.sort_by(|a, b| angle(a).total_cmp(&angle(b)));
+    return face;
+}
+
+//sutherland hodgman, keeps the part of the polygon on the inward side of the plane through point
+fn clip(polygon: &[Vec3], point: Vec3, inward: Vec3) -> Vec<Vec3> {
+    let mut clipped = Vec::new();
+    for (i, &current) in polygon.iter().enumerate() {
+        let previous = polygon[(i + polygon.len() - 1) % polygon.len()];
+        let (current_side, previous_side) = (inward.dot(current - point), inward.dot(previous - point));
+        //add where the edge crosses the plane, then the point itself if it's inside
+        if (current_side >= 0.0) != (previous_side >= 0.0) {
+            let t = previous_side / (previous_side - current_side);
+            clipped.push(previous + (current - previous) * t);
+        }
+        if current_side >= 0.0 {
+            clipped.push(current);
+        }
+    }
+    return clipped;
+}
+
+//keeps the deepest point and then whichever points are most spread out from the ones already kept
+fn reduce(mut points: Vec<ManifoldPoint>) -> Vec<ManifoldPoint> {
+    if points.len() <= MANIFOLD_MAX_POINTS {
+        return points;
+    }
+
+    let deepest = (0..points.len()).max_by(|&i, &j| points[i].depth.total_cmp(&points[j].depth)).unwrap();
+    let mut kept = vec![points.swap_remove(deepest)];
+    while kept.len() < MANIFOLD_MAX_POINTS {
+        let spread = |point: &ManifoldPoint| kept.iter().map(|other| other.point_on_s2.distance(point.point_on_s2)).fold(f32::MAX, f32::min);
+        let furthest = (0..points.len()).max_by(|&i, &j| spread(&points[i]).total_cmp(&spread(&points[j]))).unwrap();
+        kept.push(points.swap_remove(furthest));
+    }
+    return kept;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::fixtures::at;
+    use crate::collision::{collide_manifold, CollisionPlugin, Contacts, BodyType};
+
+    #[test]
+    fn box_resting_on_box_gets_four_corners() {
+        let floor = at(Collider::cuboid_from_half_extents(Vec3::new(5.0, 1.0, 5.0)), Vec3::ZERO);
+        let block = at(Collider::cuboid_from_half_extents(Vec3::ONE), Vec3::new(0.5, 1.9, 0.0));
+
+        let (_, manifold) = collide_manifold(&floor, &block).expect("block is sunk into the floor");
+        assert_eq!(manifold.points.len(), 4);
+        assert!(manifold.normal.abs_diff_eq(Vec3::Y, 0.001), "normal was {}", manifold.normal);
+        for point in &manifold.points {
+            assert!((point.depth - 0.1).abs() < 0.001, "depth was {}", point.depth);
+            assert!((point.point_on_s1.y - 1.0).abs() < 0.001, "point was {}", point.point_on_s1);
+            assert!((point.point_on_s2.x - 0.5).abs() > 0.999 && point.point_on_s2.z.abs() > 0.999, "point was {}", point.point_on_s2);
+        }
+    }
+
+    #[test]
+    fn overhanging_box_is_clipped_to_the_edge() {
+        let ledge = at(Collider::cuboid_from_half_extents(Vec3::ONE), Vec3::ZERO);
+        //sticks out half way past the edge at x = 1
+        let block = at(Collider::cuboid_from_half_extents(Vec3::ONE), Vec3::new(1.0, 1.95, 0.0));
+
+        let (_, manifold) = collide_manifold(&ledge, &block).unwrap();
+        assert_eq!(manifold.points.len(), 4);
+        assert!(manifold.points.iter().all(|point| point.point_on_s2.x >= -0.001 && point.point_on_s2.x <= 1.001));
+    }
+
+    #[test]
+    fn box_on_half_space() {
+        let mut floor = Collider::half_space();
+        floor.set_transform(&GlobalTransform::IDENTITY);
+        let block = at(Collider::cuboid_from_half_extents(Vec3::ONE), Vec3::new(3.0, 0.8, 3.0));
+
+        let (_, manifold) = collide_manifold(&block, &floor).unwrap();
+        assert_eq!(manifold.points.len(), 4);
+        assert_eq!(manifold.normal, Vec3::NEG_Y);
+        assert!(manifold.points.iter().all(|point| (point.depth - 0.2).abs() < 0.001 && point.point_on_s2.y == 0.0));
+    }
+
+    #[test]
+    fn round_shapes_use_a_single_point() {
+        let floor = at(Collider::cuboid_from_half_extents(Vec3::splat(5.0)), Vec3::ZERO);
+        let ball = at(Collider::sphere_from_radius(1.0), Vec3::new(0.0, 5.5, 0.0));
+
+        let (contact, manifold) = collide_manifold(&floor, &ball).unwrap();
+        assert_eq!(manifold.points.len(), 1);
+        assert!((manifold.points[0].depth - contact.depth).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn impulses_carry_over_between_frames() {
+        let mut app = App::new();
+        app.add_plugins(CollisionPlugin);
+        let floor = app.world.spawn((Collider::cuboid_from_half_extents(Vec3::new(5.0, 1.0, 5.0)), TransformBundle::default())).id();
+        let block = app.world.spawn((
+            Collider::cuboid_from_half_extents(Vec3::ONE),
+            TransformBundle::from_transform(Transform::from_xyz(0.0, 1.99, 0.0)),
+            BodyType::Kinematic,
+        )).id();
+
+        app.world.run_schedule(FixedUpdate);
+        for manifold in app.world.resource_mut::<Contacts>().manifolds.values_mut() {
+            for point in manifold.points.iter_mut() {
+                point.normal_impulse = 2.0;
+            }
+        }
+        app.world.run_schedule(FixedUpdate);
+
+        let manifold = app.world.resource::<Contacts>().manifold(block, floor).expect("still touching");
+        assert_eq!(manifold.points.len(), 4);
+        assert!(manifold.normal.abs_diff_eq(Vec3::NEG_Y, 0.001), "normal was {}", manifold.normal);
+        assert!(manifold.points.iter().all(|point| point.normal_impulse == 2.0));
+    }
+}
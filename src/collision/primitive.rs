@@ -0,0 +1,118 @@
+use bevy::prelude::*;
+
+use super::Collider;
+
+//planes are flat so their collider is a slab this thick with its top at the plane
+pub const PLANE_THICKNESS: f32 = 1.0;
+
+/**
+ * Put this next to a PbrBundle made from one of bevy's primitive shapes to get a Collider that matches it exactly.
+ * Build it from the same primitive as the mesh, AutoCollider::from(shape::Cube { size: 10.0 }) and so on.
+ * The Collider is added by auto_collider, entities that already have one are left alone.
+ */
+#[derive(Component, Clone, Copy, Debug)]
+pub enum AutoCollider {
+    Cube(shape::Cube),
+    Capsule(shape::Capsule),
+    UVSphere(shape::UVSphere),
+    Plane(shape::Plane),
+}
+
+impl From<shape::Cube> for AutoCollider {
+    fn from(cube: shape::Cube) -> Self {
+        return Self::Cube(cube);
+    }
+}
+
+impl From<shape::Capsule> for AutoCollider {
+    fn from(capsule: shape::Capsule) -> Self {
+        return Self::Capsule(capsule);
+    }
+}
+
+impl From<shape::UVSphere> for AutoCollider {
+    fn from(sphere: shape::UVSphere) -> Self {
+        return Self::UVSphere(sphere);
+    }
+}
+
+impl From<shape::Plane> for AutoCollider {
+    fn from(plane: shape::Plane) -> Self {
+        return Self::Plane(plane);
+    }
+}
+
+impl From<AutoCollider> for Collider {
+    fn from(primitive: AutoCollider) -> Self {
+        match primitive {
+            AutoCollider::Cube(cube) => {
+                return Collider::cuboid_from_half_extents(Vec3::splat(cube.size / 2.0));
+            },
+            AutoCollider::Capsule(capsule) => {
+                return Collider::capsule_from_radius_depth(capsule.radius, capsule.depth);
+            },
+            AutoCollider::UVSphere(sphere) => {
+                return Collider::sphere_from_radius(sphere.radius);
+            },
+            AutoCollider::Plane(plane) => {
+                //the slab hangs down from the plane so things rest on the same surface that gets drawn
+                let mut slab = Collider::cuboid_from_half_extents(Vec3::new(plane.size / 2.0, PLANE_THICKNESS / 2.0, plane.size / 2.0));
+                for point in slab.local_points.iter_mut() {
+                    *point -= Vec3::new(0.0, PLANE_THICKNESS / 2.0, 0.0);
+                }
+                slab.transformed_points = slab.local_points.clone();
+                return slab;
+            },
+        }
+    }
+}
+
+pub fn auto_collider (
+    mut commands: Commands,
+    q_waiting: Query<(Entity, &AutoCollider), Without<Collider>>,
+) {
+    for (entity, primitive) in q_waiting.iter() {
+        commands.entity(entity).insert(Collider::from(*primitive)).remove::<AutoCollider>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::CollisionPlugin;
+
+    #[test]
+    fn colliders_match_their_primitives() {
+        let cube = Collider::from(AutoCollider::from(shape::Cube { size: 10.0 }));
+        assert_eq!(cube.aabb().max, Vec3::splat(5.0));
+
+        let capsule = Collider::from(AutoCollider::from(shape::Capsule { radius: 1.0, depth: 2.0, ..default() }));
+        assert_eq!(capsule.aabb().max, Vec3::new(1.0, 2.0, 1.0));
+
+        let sphere = Collider::from(AutoCollider::from(shape::UVSphere { radius: 2.0, ..default() }));
+        assert_eq!(sphere.aabb().min, Vec3::splat(-2.0));
+
+        let plane = Collider::from(AutoCollider::from(shape::Plane::from_size(20.0)));
+        let aabb = plane.aabb();
+        assert_eq!((aabb.min, aabb.max), (Vec3::new(-10.0, -PLANE_THICKNESS, -10.0), Vec3::new(10.0, 0.0, 10.0)));
+    }
+
+    #[test]
+    fn auto_collider_adds_collider() {
+        let mut app = App::new();
+        app.add_plugins(CollisionPlugin);
+        let cube = app.world.spawn((AutoCollider::from(shape::Cube { size: 2.0 }), TransformBundle::default())).id();
+        //already has a collider so the primitive is ignored
+        let custom = app.world.spawn((
+            AutoCollider::from(shape::Cube { size: 2.0 }),
+            Collider::sphere_from_radius(0.5),
+            TransformBundle::default(),
+        )).id();
+
+        app.update();
+
+        assert!(app.world.get::<Collider>(cube).is_some());
+        assert!(app.world.get::<AutoCollider>(cube).is_none());
+        assert_eq!(app.world.get::<Collider>(custom).unwrap().aabb().max, Vec3::splat(0.5));
+    }
+}
@@ -0,0 +1,339 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use super::{convex_distance_offset, Collider, ColliderAabb, CollisionLayers};
+
+//how close a cast has to get to a surface to count as a hit
+pub const CAST_TOLERANCE: f32 = 0.001;
+pub const CAST_MAX_ITERATIONS: u32 = 32;
+
+//where a cast first touched a collider, normal points out of the surface that was hit
+#[derive(Clone, Copy, Debug)]
+pub struct CastHit {
+    pub distance: f32,
+    pub point: Vec3,
+    pub normal: Vec3,
+    //which part of a compound was hit
+    pub part: usize,
+}
+
+//same as CastHit but with the entity the collider belongs to, returned by SpatialQuery's casts
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit {
+    pub entity: Entity,
+    pub distance: f32,
+    pub point: Vec3,
+    pub normal: Vec3,
+}
+
+//decides which colliders a spatial query can hit
+#[derive(Clone, Debug)]
+pub struct QueryFilter {
+    //only colliders on one of these layers can be hit, colliders without CollisionLayers are on every layer
+    pub mask: u32,
+    pub excluded: Vec<Entity>,
+}
+
+impl QueryFilter {
+    pub fn from_mask(mask: u32) -> Self {
+        return Self { mask, excluded: Vec::new() };
+    }
+
+    pub fn excluding(mut self, entity: Entity) -> Self {
+        self.excluded.push(entity);
+        return self;
+    }
+
+    fn allows(&self, entity: Entity, layers: Option<&CollisionLayers>) -> bool {
+        let memberships = layers.map_or(CollisionLayers::ALL, |layers| layers.memberships);
+        return memberships & self.mask != 0 && !self.excluded.contains(&entity);
+    }
+}
+
+impl Default for QueryFilter {
+    fn default() -> Self {
+        return Self::from_mask(CollisionLayers::ALL);
+    }
+}
+
+impl ColliderAabb {
+    //slab test, returns how far along the ray it enters the box or 0 if it starts inside
+    pub fn ray_distance(&self, origin: Vec3, direction: Vec3) -> Option<f32> {
+        let (mut near, mut far) = (0.0_f32, f32::INFINITY);
+        for axis in 0..3 {
+            //a ray parallel to a slab is either between its planes the whole way or never, dividing by 0 would give nan on the planes
+            if direction[axis] == 0.0 {
+                if origin[axis] < self.min[axis] || origin[axis] > self.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let inverse = direction[axis].recip();
+            let (t1, t2) = ((self.min[axis] - origin[axis]) * inverse, (self.max[axis] - origin[axis]) * inverse);
+            near = near.max(t1.min(t2));
+            far = far.min(t1.max(t2));
+        }
+        if near > far {
+            return None;
+        }
+        return Some(near);
+    }
+}
+
+impl Collider {
+    //a single point, used to cast rays as a shape with no size
+    fn point(position: Vec3) -> Self {
+        let mut point = Self::sphere_from_radius(0.0);
+        point.transformed_points[0] = position;
+        return point;
+    }
+
+    pub fn cast_ray(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<CastHit> {
+        return cast_shape(&Self::point(origin), self, direction, max_distance);
+    }
+}
+
+/**
+ * Sweeps s1 along direction and returns where it first touches s2.
+ * This is conservative advancement: the gjk distance says how far s1 can safely move before it could possibly hit,
+ * so it keeps moving that far until the gap closes or it's clear it will never hit.
+ * direction has to be normalised, a shape that already overlaps s2 hits at distance 0.
+ */
+pub fn cast_shape (
+    s1: &Collider,
+    s2: &Collider,
+    direction: Vec3,
+    max_distance: f32,
+) -> Option<CastHit> {
+    return cast_shape_from(s1, s2, Vec3::ZERO, direction, max_distance);
+}
+
+//same as cast_shape but s1 starts off moved by start
+pub(super) fn cast_shape_from (
+    s1: &Collider,
+    s2: &Collider,
+    start: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+) -> Option<CastHit> {
+    //only the bits of s2 that s1 passes through on the way can be hit
+    let aabb = s1.aabb();
+    let end = start + direction * max_distance;
+    let swept = ColliderAabb { min: aabb.min + start.min(end), max: aabb.max + start.max(end) };
+
+    let mut closest: Option<CastHit> = None;
+    for p1 in s1.parts() {
+        for (i, p2) in s2.parts_near(&swept) {
+            let Some(mut hit) = cast_convex(p1, &p2, start, direction, max_distance) else {
+                continue;
+            };
+            hit.part = i;
+            if closest.map_or(true, |current| hit.distance < current.distance) {
+                closest = Some(hit);
+            }
+        }
+    }
+    return closest;
+}
+
+fn cast_convex (
+    s1: &Collider,
+    s2: &Collider,
+    start: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+) -> Option<CastHit> {
+    let mut travelled = 0.0;
+    //if the shapes overlap before we have a separation the best guess at a normal is straight back along the cast
+    let mut last = CastHit { distance: 0.0, point: s1.support(direction) + start, normal: -direction, part: 0 };
+    for _ in 0..CAST_MAX_ITERATIONS {
+        let Some(separation) = convex_distance_offset(s1, s2, start + direction * travelled) else {
+            //the last step closed the gap completely, which happens straight away against flat faces
+            last.distance = travelled;
+            return Some(last);
+        };
+
+        last = CastHit {
+            distance: travelled,
+            point: separation.point_on_s2,
+            normal: -separation.normal,
+            part: 0,
+        };
+        //the closest points' separating plane has to be crossed before they can touch,
+        //so shapes that are touching but moving apart or along each other never hit
+        let closing_speed = direction.dot(separation.normal);
+        if closing_speed <= 0.0 {
+            return None;
+        }
+        if separation.distance < CAST_TOLERANCE {
+            return Some(last);
+        }
+        let step = separation.distance / closing_speed;
+        travelled += step;
+        //if this step closes the gap there won't be another separation, so this is where s1 lands on the surface
+        last.point = separation.point_on_s1 + direction * step;
+        if travelled > max_distance {
+            return None;
+        }
+    }
+    return None;
+}
+
+/**
+ * System param for asking questions about every collider in the world.
+ * Uses the transformed points from the last time apply_transform_collider ran.
+ */
+#[derive(SystemParam)]
+pub struct SpatialQuery<'w, 's> {
+    colliders: Query<'w, 's, (Entity, &'static Collider, Option<&'static CollisionLayers>)>,
+}
+
+impl<'w, 's> SpatialQuery<'w, 's> {
+    //the first collider the ray hits within max_distance
+    pub fn cast_ray(&self, origin: Vec3, direction: Vec3, max_distance: f32, filter: &QueryFilter) -> Option<RayHit> {
+        //nothing gets hit without moving
+        let direction = direction.try_normalize()?;
+        let mut closest: Option<RayHit> = None;
+        for (entity, collider, layers) in self.colliders.iter() {
+            if !filter.allows(entity, layers) {
+                continue;
+            }
+            //skip the narrow phase for anything whose box is missed or further than the best hit
+            let limit = closest.map_or(max_distance, |hit| hit.distance);
+            match collider.aabb().ray_distance(origin, direction) {
+                Some(distance) if distance <= limit => {},
+                _ => continue,
+            }
+            if let Some(hit) = collider.cast_ray(origin, direction, limit) {
+                closest = Some(RayHit { entity, distance: hit.distance, point: hit.point, normal: hit.normal });
+            }
+        }
+        return closest;
+    }
+
+    //the first collider shape hits when moved along direction from offset, shape is a collider that has already been moved into place
+    pub fn cast_shape(&self, shape: &Collider, offset: Vec3, direction: Vec3, max_distance: f32, filter: &QueryFilter) -> Option<RayHit> {
+        //nothing gets hit without moving
+        let direction = direction.try_normalize()?;
+        let aabb = shape.aabb();
+        let end = offset + direction * max_distance;
+        let swept = ColliderAabb { min: aabb.min + offset.min(end), max: aabb.max + offset.max(end) };
+
+        let mut closest: Option<RayHit> = None;
+        for (entity, collider, layers) in self.colliders.iter() {
+            if !filter.allows(entity, layers) || !swept.intersects(&collider.aabb()) {
+                continue;
+            }
+            let limit = closest.map_or(max_distance, |hit| hit.distance);
+            if let Some(hit) = cast_shape_from(shape, collider, offset, direction, limit) {
+                closest = Some(RayHit { entity, distance: hit.distance, point: hit.point, normal: hit.normal });
+            }
+        }
+        return closest;
+    }
+
+    //true if nothing allowed by the filter is between the two points
+    pub fn line_of_sight(&self, from: Vec3, to: Vec3, filter: &QueryFilter) -> bool {
+        let offset = to - from;
+        return self.cast_ray(from, offset, offset.length(), filter).is_none();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::fixtures::at;
+
+    #[test]
+    fn ray_hits_cuboid_top() {
+        let cube = at(Collider::cuboid_from_half_extents(Vec3::splat(5.0)), Vec3::new(12.0, 0.0, 12.0));
+
+        //looking down at an angle like the camera does
+        let origin = Vec3::new(10.0, 20.0, 5.0);
+        let direction = (Vec3::new(12.0, 5.0, 12.0) - origin).normalize();
+        let hit = cube.cast_ray(origin, direction, 100.0).expect("ray should hit the top");
+        assert!(hit.point.abs_diff_eq(Vec3::new(12.0, 5.0, 12.0), 0.01), "hit at {}", hit.point);
+        assert!(hit.normal.abs_diff_eq(Vec3::Y, 0.01), "normal was {}", hit.normal);
+        assert!((hit.distance - (Vec3::new(12.0, 5.0, 12.0) - origin).length()).abs() < 0.01, "distance was {}", hit.distance);
+    }
+
+    #[test]
+    fn ray_hits_sphere() {
+        let sphere = at(Collider::sphere_from_radius(2.0), Vec3::new(0.0, 0.0, 10.0));
+        let hit = sphere.cast_ray(Vec3::ZERO, Vec3::Z, 100.0).expect("ray should hit the sphere");
+        assert!((hit.distance - 8.0).abs() < 0.01, "distance was {}", hit.distance);
+        assert!(hit.normal.abs_diff_eq(Vec3::NEG_Z, 0.01), "normal was {}", hit.normal);
+    }
+
+    #[test]
+    fn touching_shapes_only_hit_moving_together() {
+        let cube = at(Collider::cuboid_from_half_extents(Vec3::ONE), Vec3::ZERO);
+        //resting on top of the cube, just inside the cast tolerance
+        let ball = at(Collider::sphere_from_radius(1.0), Vec3::new(0.0, 2.0005, 0.0));
+
+        assert!(cast_shape(&ball, &cube, Vec3::X, 10.0).is_none());
+        assert!(cast_shape(&ball, &cube, Vec3::Y, 10.0).is_none());
+        let hit = cast_shape(&ball, &cube, Vec3::NEG_Y, 10.0).expect("moving into the cube");
+        assert!(hit.distance < 0.001, "distance was {}", hit.distance);
+    }
+
+    #[test]
+    fn axis_aligned_rays_on_box_faces() {
+        let aabb = ColliderAabb { min: Vec3::ZERO, max: Vec3::ONE };
+        //starts level with the bottom face and runs parallel to it
+        assert_eq!(aabb.ray_distance(Vec3::new(-1.0, 0.0, 0.5), Vec3::X), Some(1.0));
+        //starts on the top face
+        assert_eq!(aabb.ray_distance(Vec3::new(0.5, 1.0, 0.5), Vec3::X), Some(0.0));
+        assert_eq!(aabb.ray_distance(Vec3::new(-1.0, 1.5, 0.5), Vec3::X), None);
+        assert_eq!(aabb.ray_distance(Vec3::new(2.0, 0.5, 0.5), Vec3::X), None);
+    }
+
+    #[test]
+    fn ray_misses() {
+        let sphere = at(Collider::sphere_from_radius(2.0), Vec3::new(0.0, 0.0, 10.0));
+        assert!(sphere.cast_ray(Vec3::ZERO, Vec3::X, 100.0).is_none());
+        assert!(sphere.cast_ray(Vec3::ZERO, Vec3::NEG_Z, 100.0).is_none());
+        assert!(sphere.cast_ray(Vec3::ZERO, Vec3::Z, 5.0).is_none());
+    }
+
+    #[test]
+    fn spatial_query_picks_closest() {
+        let mut world = World::new();
+        let near = world.spawn(at(Collider::sphere_from_radius(1.0), Vec3::new(0.0, 0.0, 5.0))).id();
+        world.spawn(at(Collider::sphere_from_radius(1.0), Vec3::new(0.0, 0.0, 10.0)));
+        let layered = world.spawn((
+            at(Collider::sphere_from_radius(1.0), Vec3::new(0.0, 0.0, 2.0)),
+            CollisionLayers::new(1 << 3, CollisionLayers::ALL),
+        )).id();
+
+        let mut state: bevy::ecs::system::SystemState<SpatialQuery> = bevy::ecs::system::SystemState::new(&mut world);
+        let query = state.get(&world);
+
+        //the layered sphere is nearest but the filter skips it
+        let filter = QueryFilter::from_mask(!(1 << 3));
+        let hit = query.cast_ray(Vec3::ZERO, Vec3::Z, 100.0, &filter).expect("should hit a sphere");
+        assert_eq!(hit.entity, near);
+        assert!((hit.distance - 4.0).abs() < 0.01, "distance was {}", hit.distance);
+
+        let hit = query.cast_ray(Vec3::ZERO, Vec3::Z, 100.0, &QueryFilter::default()).unwrap();
+        assert_eq!(hit.entity, layered);
+
+        let filter = QueryFilter::default().excluding(layered).excluding(near);
+        assert!(!query.line_of_sight(Vec3::ZERO, Vec3::new(0.0, 0.0, 20.0), &filter));
+        assert!(query.line_of_sight(Vec3::ZERO, Vec3::new(0.0, 0.0, 7.0), &filter));
+    }
+    #[test]
+    fn zero_length_queries_hit_nothing() {
+        let mut world = World::new();
+        world.spawn(at(Collider::sphere_from_radius(1.0), Vec3::new(0.0, 0.0, 2.0)));
+
+        let mut state: bevy::ecs::system::SystemState<SpatialQuery> = bevy::ecs::system::SystemState::new(&mut world);
+        let query = state.get(&world);
+
+        let filter = QueryFilter::default();
+        let point = Vec3::new(0.0, 0.0, 1.5);
+        assert!(query.line_of_sight(point, point, &filter));
+        assert!(query.cast_ray(Vec3::ZERO, Vec3::ZERO, 10.0, &filter).is_none());
+        assert!(query.cast_shape(&Collider::sphere_from_radius(0.5), Vec3::ZERO, Vec3::ZERO, 10.0, &filter).is_none());
+    }
+}
@@ -0,0 +1,159 @@
+use bevy::prelude::*;
+
+use super::{BodyType, Contacts, RigidBody};
+
+/**
+ * Marks a collider as a trigger volume, like an area of effect spell or a pickup zone.
+ * Sensors still show up in Contacts and send collision events but never push anything or get pushed.
+ */
+#[derive(Component, Default)]
+pub struct Sensor;
+
+/**
+ * Pushes dynamic bodies straight out along the contact normal by the penetration depth.
+ * Two dynamic bodies split the push between them, static and kinematic bodies never move so a dynamic body takes all of it.
+ * Movement along the surface is left alone, so something walking into a wall at an angle keeps sliding along it.
+ * The push is worked out in world space and turned into the parent's space for child colliders.
+ * Entities with a RigidBody are pushed out by solve_contacts instead and count as immovable here.
+ */
+pub fn resolve_contacts (
+    contacts: Res<Contacts>,
+    mut q_bodies: Query<(&mut Transform, &BodyType, Option<&Parent>), Without<RigidBody>>,
+    q_global: Query<&GlobalTransform>,
+    q_sensors: Query<(), With<Sensor>>,
+) {
+    for (e1, e2, contact) in contacts.iter() {
+        if q_sensors.contains(e1) || q_sensors.contains(e2) {
+            continue;
+        }
+        let dynamic1 = matches!(q_bodies.get(e1), Ok((_, BodyType::Dynamic, _)));
+        let dynamic2 = matches!(q_bodies.get(e2), Ok((_, BodyType::Dynamic, _)));
+        let share = match (dynamic1, dynamic2) {
+            (true, true) => 0.5,
+            (true, false) | (false, true) => 1.0,
+            (false, false) => continue,
+        };
+
+        //normal points from e1 to e2 so e1 goes back along it and e2 goes forward
+        let push = contact.normal * contact.depth * share;
+        if dynamic1 {
+            let (mut transform, _, parent) = q_bodies.get_mut(e1).unwrap();
+            transform.translation -= to_local(push, parent, &q_global);
+        }
+        if dynamic2 {
+            let (mut transform, _, parent) = q_bodies.get_mut(e2).unwrap();
+            transform.translation += to_local(push, parent, &q_global);
+        }
+    }
+}
+
+//turns a world space push into the space the entity's Transform lives in
+fn to_local (push: Vec3, parent: Option<&Parent>, q_global: &Query<&GlobalTransform>) -> Vec3 {
+    return match parent.and_then(|parent| q_global.get(parent.get()).ok()) {
+        Some(global) => global.affine().inverse().transform_vector3(push),
+        None => push,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::{Collider, CollisionPlugin, CollisionStarted};
+
+    fn spawn(app: &mut App, collider: Collider, translation: Vec3) -> Entity {
+        let transform = Transform::from_translation(translation);
+        return app.world.spawn((collider, transform, GlobalTransform::from(transform))).id();
+    }
+
+    #[test]
+    fn dynamic_pushed_out_of_static() {
+        let mut app = App::new();
+        app.add_plugins(CollisionPlugin);
+        let wall = spawn(&mut app, Collider::cuboid_from_half_extents(Vec3::splat(5.0)), Vec3::ZERO);
+        let player = spawn(&mut app, Collider::sphere_from_radius(1.0), Vec3::new(5.5, 0.0, 2.0));
+        app.world.entity_mut(player).insert(BodyType::Dynamic);
+
+        app.world.run_schedule(FixedUpdate);
+
+        //only the x overlap gets undone, z is left alone so the player slides along the wall
+        let moved = app.world.get::<Transform>(player).unwrap().translation;
+        assert!(moved.abs_diff_eq(Vec3::new(6.0, 0.0, 2.0), 0.01), "player ended up at {}", moved);
+        assert_eq!(app.world.get::<Transform>(wall).unwrap().translation, Vec3::ZERO);
+    }
+
+    #[test]
+    fn sensor_reports_without_pushing() {
+        let mut app = App::new();
+        app.add_plugins(CollisionPlugin);
+        let zone = spawn(&mut app, Collider::cuboid_from_half_extents(Vec3::splat(5.0)), Vec3::ZERO);
+        let player = spawn(&mut app, Collider::sphere_from_radius(1.0), Vec3::new(4.5, 0.0, 0.0));
+        app.world.entity_mut(zone).insert(Sensor);
+        app.world.entity_mut(player).insert(BodyType::Dynamic);
+
+        app.world.run_schedule(FixedUpdate);
+
+        assert_eq!(app.world.get::<Transform>(player).unwrap().translation, Vec3::new(4.5, 0.0, 0.0));
+        assert!(app.world.resource::<Contacts>().contains(zone, player));
+        let started = app.world.resource::<Events<CollisionStarted>>();
+        assert_eq!(started.get_reader().iter(started).count(), 1);
+    }
+
+    #[test]
+    fn kinematic_pushes_without_being_pushed() {
+        let mut app = App::new();
+        app.add_plugins(CollisionPlugin);
+        let pusher = spawn(&mut app, Collider::cuboid_from_half_extents(Vec3::ONE), Vec3::ZERO);
+        let block = spawn(&mut app, Collider::cuboid_from_half_extents(Vec3::ONE), Vec3::new(1.5, 0.0, 0.0));
+        let wall = spawn(&mut app, Collider::cuboid_from_half_extents(Vec3::ONE), Vec3::new(-1.5, 0.0, 0.0));
+        app.world.entity_mut(pusher).insert(BodyType::Kinematic);
+        app.world.entity_mut(block).insert(BodyType::Dynamic);
+
+        app.world.run_schedule(FixedUpdate);
+
+        assert_eq!(app.world.get::<Transform>(pusher).unwrap().translation, Vec3::ZERO);
+        let moved = app.world.get::<Transform>(block).unwrap().translation;
+        assert!(moved.abs_diff_eq(Vec3::new(2.0, 0.0, 0.0), 0.01), "block ended up at {}", moved);
+        //kinematic against static still counts as touching, it just doesn't push anything
+        assert!(app.world.resource::<Contacts>().contains(pusher, wall));
+    }
+
+    #[test]
+    fn dynamic_pair_share_push() {
+        let mut app = App::new();
+        app.add_plugins(CollisionPlugin);
+        let s1 = spawn(&mut app, Collider::cuboid_from_half_extents(Vec3::ONE), Vec3::ZERO);
+        let s2 = spawn(&mut app, Collider::cuboid_from_half_extents(Vec3::ONE), Vec3::new(1.0, 0.0, 0.0));
+        app.world.entity_mut(s1).insert(BodyType::Dynamic);
+        app.world.entity_mut(s2).insert(BodyType::Dynamic);
+
+        app.world.run_schedule(FixedUpdate);
+
+        let (p1, p2) = (app.world.get::<Transform>(s1).unwrap().translation, app.world.get::<Transform>(s2).unwrap().translation);
+        assert!(p1.abs_diff_eq(Vec3::new(-0.5, 0.0, 0.0), 0.01), "s1 ended up at {}", p1);
+        assert!(p2.abs_diff_eq(Vec3::new(1.5, 0.0, 0.0), 0.01), "s2 ended up at {}", p2);
+    }
+
+    #[test]
+    fn parented_dynamic_pushed_in_parent_space() {
+        let mut app = App::new();
+        app.add_plugins(CollisionPlugin);
+        spawn(&mut app, Collider::cuboid_from_half_extents(Vec3::splat(5.0)), Vec3::ZERO);
+
+        //the parent is turned so the child's local z points along world x
+        let parent_transform = Transform::from_xyz(3.5, 0.0, 0.0).with_rotation(Quat::from_rotation_y(std::f32::consts::FRAC_PI_2));
+        let parent = app.world.spawn((parent_transform, GlobalTransform::from(parent_transform))).id();
+        let local = Transform::from_xyz(0.0, 0.0, 2.0);
+        let child = app.world.spawn((
+            Collider::sphere_from_radius(1.0),
+            BodyType::Dynamic,
+            local,
+            GlobalTransform::from(parent_transform).mul_transform(local),
+        )).id();
+        app.world.entity_mut(parent).push_children(&[child]);
+
+        app.world.run_schedule(FixedUpdate);
+
+        let moved = app.world.get::<Transform>(child).unwrap().translation;
+        assert!(moved.abs_diff_eq(Vec3::new(0.0, 0.0, 2.5), 0.01), "child ended up at {}", moved);
+    }
+}
@@ -0,0 +1,186 @@
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+
+use super::{BodyType, CollisionEnded, CollisionStarted, Contacts, RigidBody, Sensor};
+
+//bodies moving and spinning slower than these count as resting
+pub const SLEEP_LINEAR_THRESHOLD: f32 = 0.1;
+pub const SLEEP_ANGULAR_THRESHOLD: f32 = 0.1;
+//seconds every body in an island has to rest for before the island falls asleep
+pub const TIME_TO_SLEEP: f32 = 0.5;
+
+//groups of dynamic bodies touching each other, kept as a union find so joining two islands is cheap
+#[derive(Default)]
+struct Islands {
+    parents: HashMap<Entity, Entity>,
+}
+
+impl Islands {
+    fn find(&mut self, entity: Entity) -> Entity {
+        let mut root = entity;
+        while let Some(&parent) = self.parents.get(&root) {
+            if parent == root {
+                break;
+            }
+            root = parent;
+        }
+        //everything on the way points straight at the root next time
+        let mut current = entity;
+        while current != root {
+            current = self.parents.insert(current, root).unwrap_or(root);
+        }
+        return root;
+    }
+
+    fn join(&mut self, e1: Entity, e2: Entity) {
+        let (r1, r2) = (self.find(e1), self.find(e2));
+        self.parents.insert(r1.max(r2), r1.min(r2));
+    }
+}
+
+/**
+ * Puts dynamic rigid bodies to sleep once they have stopped moving and wakes them back up when something touches them.
+ * Bodies that are touching form an island, an island only sleeps once all of its bodies have been resting for TIME_TO_SLEEP
+ * and waking any one of them wakes the whole island, so a stack never has its bottom asleep while the top is still falling.
+ * Static and kinematic colliders don't join islands, otherwise everything resting on the floor would be one island.
+ * Sleeping bodies are left out of gravity, the solver and integration, and collision_update doesn't test pairs where
+ * neither side can move, it keeps their contacts from when they fell asleep.
+ * A body wakes when a contact with it starts or ends, or when it has been given some velocity.
+ */
+pub fn update_sleep (
+    fixed_time: Res<FixedTime>,
+    contacts: Res<Contacts>,
+    mut e_started: EventReader<CollisionStarted>,
+    mut e_ended: EventReader<CollisionEnded>,
+    mut q_bodies: Query<(Entity, &mut RigidBody, &BodyType)>,
+    q_sensors: Query<(), With<Sensor>>,
+) {
+    let dt = fixed_time.period.as_secs_f32();
+    let mut touched: HashSet<Entity> = HashSet::default();
+    for event in e_started.iter() {
+        touched.extend([event.e1, event.e2]);
+    }
+    for event in e_ended.iter() {
+        touched.extend([event.e1, event.e2]);
+    }
+
+    let mut islands = Islands::default();
+    for (entity, mut body, body_type) in q_bodies.iter_mut() {
+        if *body_type != BodyType::Dynamic {
+            continue;
+        }
+        let resting = body.linear_velocity.length() < SLEEP_LINEAR_THRESHOLD && body.angular_velocity.length() < SLEEP_ANGULAR_THRESHOLD;
+        if touched.contains(&entity) || !resting {
+            body.sleeping = false;
+            body.sleep_time = 0.0;
+        } else if !body.sleeping {
+            body.sleep_time += dt;
+        }
+        islands.parents.insert(entity, entity);
+    }
+
+    for &(e1, e2) in contacts.pairs.keys() {
+        if islands.parents.contains_key(&e1) && islands.parents.contains_key(&e2) && !q_sensors.contains(e1) && !q_sensors.contains(e2) {
+            islands.join(e1, e2);
+        }
+    }
+
+    //an island stays awake while any one of its bodies is
+    let mut awake: HashMap<Entity, bool> = HashMap::default();
+    for (entity, body, body_type) in q_bodies.iter() {
+        if *body_type == BodyType::Dynamic {
+            let island = awake.entry(islands.find(entity)).or_insert(false);
+            *island |= !body.sleeping && body.sleep_time < TIME_TO_SLEEP;
+        }
+    }
+
+    for (entity, mut body, body_type) in q_bodies.iter_mut() {
+        if *body_type != BodyType::Dynamic {
+            continue;
+        }
+        if awake[&islands.find(entity)] {
+            if body.sleeping {
+                body.sleeping = false;
+                body.sleep_time = 0.0;
+            }
+        } else if !body.sleeping {
+            body.sleeping = true;
+            body.linear_velocity = Vec3::ZERO;
+            body.angular_velocity = Vec3::ZERO;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::{BroadPhaseStats, Collider, CollisionPlugin};
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins(CollisionPlugin);
+        app.world.spawn((Collider::cuboid_from_half_extents(Vec3::new(50.0, 1.0, 50.0)), TransformBundle::default()));
+        return app;
+    }
+
+    fn spawn_block(app: &mut App, translation: Vec3) -> Entity {
+        let block = Collider::cuboid_from_half_extents(Vec3::ONE);
+        return app.world.spawn((
+            RigidBody::new(1.0, &block),
+            block,
+            BodyType::Dynamic,
+            TransformBundle::from_transform(Transform::from_translation(translation)),
+        )).id();
+    }
+
+    fn run(app: &mut App, ticks: u32) {
+        for _ in 0..ticks {
+            app.world.run_schedule(FixedUpdate);
+        }
+    }
+
+    fn sleeping(app: &App, entity: Entity) -> bool {
+        return app.world.get::<RigidBody>(entity).unwrap().sleeping;
+    }
+
+    #[test]
+    fn stacks_sleep_and_wake_together() {
+        let mut app = app();
+        let bottom = spawn_block(&mut app, Vec3::new(0.0, 2.0, 0.0));
+        let top = spawn_block(&mut app, Vec3::new(0.0, 4.0, 0.0));
+
+        run(&mut app, 120);
+        assert!(sleeping(&app, bottom) && sleeping(&app, top));
+        //the pairs haven't moved so the broad phase doesn't hand them on
+        assert_eq!(app.world.resource::<BroadPhaseStats>().candidate_pairs, 0);
+        //the contacts they fell asleep with are kept
+        assert!(app.world.resource::<Contacts>().contains(bottom, top));
+        let resting = app.world.get::<Transform>(top).unwrap().translation;
+        run(&mut app, 60);
+        assert_eq!(app.world.get::<Transform>(top).unwrap().translation, resting);
+
+        //knocking the top block wakes the one underneath it too
+        app.world.get_mut::<RigidBody>(top).unwrap().linear_velocity = Vec3::new(2.0, 0.0, 0.0);
+        run(&mut app, 1);
+        assert!(!sleeping(&app, bottom) && !sleeping(&app, top));
+    }
+
+    #[test]
+    fn sleeping_bodies_wake_when_hit() {
+        let mut app = app();
+        let block = spawn_block(&mut app, Vec3::new(0.0, 2.0, 0.0));
+        //far enough away to be its own island
+        let other = spawn_block(&mut app, Vec3::new(10.0, 2.0, 0.0));
+        run(&mut app, 60);
+        assert!(sleeping(&app, block) && sleeping(&app, other));
+
+        let ball = Collider::sphere_from_radius(0.5);
+        let body = RigidBody::new(1.0, &ball).with_gravity_scale(0.0).with_velocity(Vec3::new(5.0, 0.0, 0.0));
+        app.world.spawn((body, ball, BodyType::Dynamic, TransformBundle::from_transform(Transform::from_xyz(-3.0, 2.0, 0.0))));
+
+        run(&mut app, 30);
+        assert!(!sleeping(&app, block), "the ball went straight past");
+        assert!(app.world.get::<RigidBody>(block).unwrap().linear_velocity.x > 0.0);
+        assert!(sleeping(&app, other));
+    }
+}
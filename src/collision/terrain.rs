@@ -0,0 +1,212 @@
+use std::borrow::Cow;
+
+use bevy::prelude::*;
+
+use super::{Collider, ColliderAabb, Contact, Shapes};
+
+//how far below the surface each heightfield triangle reaches, gives the narrow phase something solid to push things out of
+pub const HEIGHTFIELD_THICKNESS: f32 = 1.0;
+
+impl Collider {
+    /**
+     * An infinite floor, everything below the local xz plane is solid and local y is up like bevy's shape::Plane.
+     * Rotating the entity tilts the floor, scale makes no difference.
+     */
+    pub fn half_space() -> Self {
+        let points = vec![Vec3::ZERO, Vec3::Y];
+        return Self {
+            shape: Shapes::HalfSpace,
+            local_points: points.clone(),
+            transformed_points: points,
+            scale: 1.0,
+        }
+    }
+
+    /**
+     * Terrain built from a grid of heights, heights[row][column] with rows running along z and columns along x.
+     * The grid is centred on the entity and stretched to cover size in x and z.
+     * Every triangle of the grid is treated as its own convex piece with HEIGHTFIELD_THICKNESS of solid ground under it.
+     */
+    pub fn heightfield(heights: Vec<Vec<f32>>, size: Vec2) -> Self {
+        let rows = heights.len();
+        let columns = heights.first().map_or(0, |row| row.len());
+        assert!(rows >= 2 && columns >= 2 && heights.iter().all(|row| row.len() == columns), "a heightfield needs a grid of at least 2 x 2 heights");
+
+        let spacing = size / Vec2::new((columns - 1) as f32, (rows - 1) as f32);
+        let mut points = vec![Vec3::ZERO, Vec3::Y];
+        for (row, row_heights) in heights.iter().enumerate() {
+            for (column, height) in row_heights.iter().enumerate() {
+                points.push(Vec3::new(column as f32 * spacing.x - size.x / 2.0, *height, row as f32 * spacing.y - size.y / 2.0));
+            }
+        }
+        return Self {
+            shape: Shapes::Heightfield { rows, columns },
+            local_points: points.clone(),
+            transformed_points: points,
+            scale: 1.0,
+        }
+    }
+
+    //a point on the surface and the direction out of the solid side, for half-spaces and heightfields
+    fn surface(&self) -> (Vec3, Vec3) {
+        let origin = self.transformed_points[0];
+        return (origin, (self.transformed_points[1] - origin).normalize_or_zero());
+    }
+
+    /**
+     * The convex pieces of this collider that could touch anything inside region, numbered the same way as Contact::part_s1.
+     * Heightfields get cut up into a prism for each triangle, anything else is the same as parts().
+     */
+    pub(super) fn parts_near(&self, region: &ColliderAabb) -> Vec<(usize, Cow<'_, Collider>)> {
+        let Shapes::Heightfield { rows, columns } = self.shape else {
+            return self.parts().into_iter().enumerate().map(|(i, part)| (i, Cow::Borrowed(part))).collect();
+        };
+
+        let points = &self.transformed_points[2..];
+        let below = -self.surface().1 * HEIGHTFIELD_THICKNESS;
+        let mut parts = Vec::new();
+        for row in 0..rows - 1 {
+            for column in 0..columns - 1 {
+                let corner = |r: usize, c: usize| points[(row + r) * columns + column + c];
+                let (a, b, c, d) = (corner(0, 0), corner(0, 1), corner(1, 0), corner(1, 1));
+                for (i, triangle) in [[a, b, c], [b, d, c]].into_iter().enumerate() {
+                    let prism: Vec<Vec3> = triangle.iter().flat_map(|&point| [point, point + below]).collect();
+                    let aabb = prism.iter().fold(
+                        ColliderAabb { min: Vec3::INFINITY, max: Vec3::NEG_INFINITY },
+                        |aabb, &point| ColliderAabb { min: aabb.min.min(point), max: aabb.max.max(point) },
+                    );
+                    if region.intersects(&aabb) {
+                        parts.push(((row * (columns - 1) + column) * 2 + i, Cow::Owned(Collider::poly_from_points(prism))));
+                    }
+                }
+            }
+        }
+        return parts;
+    }
+}
+
+//a half-space only has a top if it faces straight along an axis, otherwise its box is all of space
+pub(super) fn half_space_aabb(plane: &Collider) -> ColliderAabb {
+    let (origin, normal) = plane.surface();
+    let mut aabb = ColliderAabb::INFINITE;
+    for axis in 0..3 {
+        if normal[axis] > 1.0 - f32::EPSILON {
+            aabb.max[axis] = origin[axis];
+        } else if normal[axis] < -1.0 + f32::EPSILON {
+            aabb.min[axis] = origin[axis];
+        }
+    }
+    return aabb;
+}
+
+//the grid points plus the thickness underneath them
+pub(super) fn heightfield_support(heightfield: &Collider, d: Vec3) -> Vec3 {
+    let up = heightfield.surface().1;
+    let top = heightfield.transformed_points[2..].iter().copied().max_by(|a, b| a.dot(d).total_cmp(&b.dot(d))).unwrap();
+    if up.dot(d) < 0.0 {
+        return top - up * HEIGHTFIELD_THICKNESS;
+    }
+    return top;
+}
+
+/**
+ * How far the lowest point of other is above the half-space's surface, negative when it's underneath.
+ * other is treated as if it had been moved by offset, the lowest point and the surface normal are returned with the height.
+ */
+pub(super) fn half_space_height(plane: &Collider, other: &Collider, offset: Vec3) -> (f32, Vec3, Vec3) {
+    let (origin, normal) = plane.surface();
+    let lowest = other.support(-normal) + offset;
+    return (normal.dot(lowest - origin), lowest, normal);
+}
+
+//contact between a half-space (s1) and a convex shape (s2), the normal is always the surface normal
+pub(super) fn half_space_contact(plane: &Collider, other: &Collider) -> Option<Contact> {
+    let (height, lowest, normal) = half_space_height(plane, other, Vec3::ZERO);
+    if height >= 0.0 {
+        return None;
+    }
+    return Some(Contact {
+        depth: -height,
+        normal,
+        point_on_s1: lowest - normal * height,
+        point_on_s2: lowest,
+        part_s1: 0,
+        part_s2: 0,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::{collide, gjk_distance};
+
+    fn at(mut collider: Collider, transform: Transform) -> Collider {
+        collider.set_transform(&GlobalTransform::from(transform));
+        return collider;
+    }
+
+    //a ramp going up 4 units over 8 along x, flat along z
+    fn ramp() -> Collider {
+        let heights = vec![vec![0.0, 2.0, 4.0]; 3];
+        return at(Collider::heightfield(heights, Vec2::splat(8.0)), Transform::IDENTITY);
+    }
+
+    #[test]
+    fn sphere_sinks_into_half_space() {
+        let floor = at(Collider::half_space(), Transform::from_xyz(0.0, 1.0, 0.0));
+        let sphere = at(Collider::sphere_from_radius(1.0), Transform::from_xyz(3.0, 1.5, -2.0));
+
+        let hit = collide(&floor, &sphere).expect("sphere is half under the floor");
+        assert!((hit.depth - 0.5).abs() < 0.001, "depth was {}", hit.depth);
+        assert_eq!(hit.normal, Vec3::Y);
+
+        //the other way round the normal points down into the floor
+        let hit = collide(&sphere, &floor).unwrap();
+        assert_eq!(hit.normal, Vec3::NEG_Y);
+
+        let above = at(Collider::sphere_from_radius(1.0), Transform::from_xyz(0.0, 4.0, 0.0));
+        assert!(collide(&floor, &above).is_none());
+        let separation = gjk_distance(&above, &floor).expect("sphere is above the floor");
+        assert!((separation.distance - 2.0).abs() < 0.001, "distance was {}", separation.distance);
+    }
+
+    #[test]
+    fn tilted_half_space() {
+        //turned 45 degrees about z so the surface normal points up and to the left
+        let floor = at(Collider::half_space(), Transform::from_rotation(Quat::from_rotation_z(std::f32::consts::FRAC_PI_4)));
+        let cube = at(Collider::cuboid_from_half_extents(Vec3::ONE), Transform::from_xyz(2.0, 0.0, 0.0));
+
+        let hit = collide(&floor, &cube).expect("the cube's bottom right corner is under the floor");
+        assert!(hit.normal.abs_diff_eq(Vec3::new(-1.0, 1.0, 0.0).normalize(), 0.001), "normal was {}", hit.normal);
+        assert!((hit.point_on_s2 - Vec3::new(3.0, -1.0, 0.0)).truncate().length() < 0.001, "point was {}", hit.point_on_s2);
+        assert_eq!(floor.aabb(), ColliderAabb::INFINITE);
+    }
+
+    #[test]
+    fn rays_hit_the_ground() {
+        let floor = at(Collider::half_space(), Transform::IDENTITY);
+        let hit = floor.cast_ray(Vec3::new(5.0, 10.0, 5.0), Vec3::new(1.0, -1.0, 0.0).normalize(), 100.0).expect("ray points at the floor");
+        assert!(hit.point.abs_diff_eq(Vec3::new(15.0, 0.0, 5.0), 0.01), "hit at {}", hit.point);
+        assert!(hit.normal.abs_diff_eq(Vec3::Y, 0.001), "normal was {}", hit.normal);
+        assert!(floor.cast_ray(Vec3::new(5.0, 10.0, 5.0), Vec3::Y, 100.0).is_none());
+
+        //straight down onto the middle of the ramp
+        let hit = ramp().cast_ray(Vec3::new(1.0, 10.0, 0.5), Vec3::NEG_Y, 100.0).expect("ray points at the ramp");
+        assert!((hit.point.y - 2.5).abs() < 0.01, "hit at {}", hit.point);
+        assert!(hit.normal.abs_diff_eq(Vec3::new(-1.0, 2.0, 0.0).normalize(), 0.01), "normal was {}", hit.normal);
+    }
+
+    #[test]
+    fn sphere_rests_on_heightfield_slope() {
+        let ramp = ramp();
+        //the slope is 2 units up for every 4 along, so at x = 1 the surface is at 2.5
+        let sphere = at(Collider::sphere_from_radius(1.0), Transform::from_xyz(1.0, 3.0, 0.0));
+
+        let hit = collide(&ramp, &sphere).expect("sphere is sunk into the ramp");
+        assert!(hit.normal.abs_diff_eq(Vec3::new(-1.0, 2.0, 0.0).normalize(), 0.01), "normal was {}", hit.normal);
+
+        let far_away = at(Collider::sphere_from_radius(1.0), Transform::from_xyz(20.0, 3.0, 0.0));
+        assert!(collide(&ramp, &far_away).is_none());
+        assert!(ramp.aabb().min.y == -HEIGHTFIELD_THICKNESS && ramp.aabb().max == Vec3::new(4.0, 4.0, 4.0));
+    }
+}
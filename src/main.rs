@@ -1,3 +1,4 @@
+use bevy::diagnostic::LogDiagnosticsPlugin;
 use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
 
@@ -5,25 +6,60 @@ mod collision;
 use collision::*;
 
 pub const CAMERA_SPEED: f32 = 15.0;
+//how many times a second movement and collisions are worked out
+pub const PHYSICS_TICK_RATE: f32 = 60.0;
+
+//enough to get on top of the obstacle cubes, which sit half in the floor so their tops are 5 up
+pub const JUMP_SPEED: f32 = 11.0;
+//jumps still work for this long after walking off an edge
+pub const COYOTE_TIME: f32 = 0.1;
+//pressing jump this long before landing still jumps as soon as the player lands
+pub const JUMP_BUFFER_TIME: f32 = 0.15;
+//how far the camera drops for every unit/s the player was falling when they land, up to LANDING_DIP_MAX
+pub const LANDING_DIP_PER_SPEED: f32 = 0.03;
+pub const LANDING_DIP_MAX: f32 = 0.6;
+//how quickly the camera settles back after a landing, higher is faster
+pub const LANDING_DIP_RECOVERY: f32 = 8.0;
+
+//collision layers
+pub const LAYER_PLAYER: u32 = 1 << 0;
+pub const LAYER_SPELL: u32 = 1 << 1;
+pub const LAYER_STATIC: u32 = 1 << 2;
 
 fn main() {
     App::new()
-        .add_plugins((DefaultPlugins, CollisionPlugin))
+        .add_plugins((DefaultPlugins, CollisionPlugin, CollisionDebugPlugin))
+        //prints CollisionDiagnostics every second
+        .add_plugins(LogDiagnosticsPlugin::default())
         .add_event::<MouseFire>()
+        .add_event::<PlayerLanded>()
         .init_resource::<CursorToPlane>()
+        .insert_resource(FixedTime::new_from_secs(1.0 / PHYSICS_TICK_RATE))
         .add_systems(Startup, scene_setup)
         .add_systems(Startup, cursor_setup)
         .add_systems(Update, controller)
         .add_systems(Update, cursor_update)
         .add_systems(Update, wand_aiming)
-        .add_systems(Update, spell_update)
-        .add_systems(Update, apply_vel)
+        .add_systems(Update, landing_dip)
+        .add_systems(FixedUpdate, apply_vel.in_set(PhysicsSet::Move))
+        .add_systems(FixedUpdate, (
+            player_jump.before(move_characters),
+            player_landing.after(move_characters),
+        ).in_set(PhysicsSet::Move))
+        //in the same tick as the collision events so none get missed
+        .add_systems(FixedUpdate, spell_update.after(PhysicsSet::Collide))
         .run();
 }
 
 #[derive(Event)]
 pub struct MouseFire;
 
+//sent when the player touches down after being in the air, speed is how fast they were falling
+#[derive(Event)]
+pub struct PlayerLanded {
+    pub speed: f32,
+}
+
 #[derive(Resource, Default)]
 pub struct CursorToPlane {
     pos: Vec3,
@@ -32,6 +68,67 @@ pub struct CursorToPlane {
 #[derive(Component)]
 pub struct Player;
 
+//the player's up and down movement, walking is set straight on the CharacterController by controller
+#[derive(Component, Default)]
+pub struct PlayerJump {
+    vertical_speed: f32,
+    //how long since the player was last on the ground
+    airborne_time: f32,
+    //how long ago jump was pressed if it hasn't been used yet
+    buffered: Option<f32>,
+    airborne: bool,
+}
+
+impl PlayerJump {
+    //the jump happens on the next tick the player is allowed to, which might be after they land
+    pub fn press(&mut self) {
+        self.buffered = Some(0.0);
+    }
+
+    //counts down coyote time and the buffered jump and returns the vertical speed to move with this tick
+    pub fn before_move(&mut self, grounded: bool, gravity: f32, dt: f32) -> f32 {
+        if grounded {
+            self.airborne_time = 0.0;
+        } else {
+            self.airborne_time += dt;
+            self.vertical_speed += gravity * dt;
+        }
+
+        //a jump pressed in the air is kept for a moment in case the player is about to land
+        if let Some(pressed) = self.buffered {
+            if self.airborne_time <= COYOTE_TIME {
+                self.vertical_speed = JUMP_SPEED;
+                //stops coyote time giving a second jump straight after the first
+                self.airborne_time = COYOTE_TIME + dt;
+                self.buffered = None;
+            } else if pressed + dt > JUMP_BUFFER_TIME {
+                self.buffered = None;
+            } else {
+                self.buffered = Some(pressed + dt);
+            }
+        }
+        return self.vertical_speed;
+    }
+
+    //picks out the tick the player gets to the ground, returns how fast they were falling if it's this one
+    pub fn after_move(&mut self, grounded: bool, ceiling: bool) -> Option<f32> {
+        //bumping a head on something stops the jump there instead of the player hanging under it
+        if ceiling && self.vertical_speed > 0.0 {
+            self.vertical_speed = 0.0;
+        }
+        if !grounded {
+            self.airborne = true;
+            return None;
+        }
+
+        let landed = if self.airborne { Some(-self.vertical_speed) } else { None };
+        self.airborne = false;
+        //whatever speed was left over from falling or a jump that got cut short by a ledge
+        self.vertical_speed = 0.0;
+        return landed;
+    }
+}
+
 #[derive(Component)]
 pub struct GroundPlane;
 
@@ -73,15 +170,24 @@ fn scene_setup(
         ..default()
     }).id();
 
+    let player_shape = shape::Capsule { radius: 1.0, ..default() };
     let player = commands.spawn((
         PbrBundle {
-            mesh: meshes.add(Mesh::from(shape::Capsule { radius: 1.0, ..default() })),
+            mesh: meshes.add(Mesh::from(player_shape)),
             material: materials.add(Color::rgb(0.8, 0.7, 0.6).into()),
-            transform: Transform::from_xyz(0.0, 0.5, 0.0),
+            //stood on the floor, the capsule is 3 tall
+            transform: Transform::from_xyz(0.0, 1.5, 0.0),
             ..default()
         },
         Player,
-        Velocity {vel: Vec3::ZERO},
+        PlayerJump::default(),
+        CharacterController::default(),
+        //the camera hangs off the player so this keeps the whole view smooth
+        Interpolated::default(),
+        AutoCollider::from(player_shape),
+        //the player's own spells go straight through them
+        CollisionLayers::new(LAYER_PLAYER, CollisionLayers::ALL & !LAYER_SPELL),
+        BodyType::Kinematic,
     )).id();
 
     commands.entity(player).push_children(&[camera]);
@@ -95,25 +201,36 @@ fn scene_setup(
             ..default()
         },
         GroundPlane,
+        //a half-space rather than an AutoCollider so nothing can fall off the edge
+        Collider::half_space(),
+        CollisionLayers::new(LAYER_STATIC, CollisionLayers::ALL & !LAYER_STATIC),
+        BodyType::Static,
     ));
 
     //obstacles
+    let obstacle_shape = shape::Cube { size: 10.0 };
     commands.spawn((
         PbrBundle {
-            mesh: meshes.add(shape::Cube { size: 10.0 }.into()),
+            mesh: meshes.add(obstacle_shape.into()),
             material: materials.add(Color::rgb(0.0, 1.0, 0.5).into()),
             transform: Transform::from_translation(Vec3::new(12.0, 0.0, 12.0)),
             ..default()
         },
+        AutoCollider::from(obstacle_shape),
+        CollisionLayers::new(LAYER_STATIC, CollisionLayers::ALL & !LAYER_STATIC),
+        BodyType::Static,
     ));
 
     commands.spawn((
         PbrBundle {
-            mesh: meshes.add(shape::Cube { size: 10.0 }.into()),
+            mesh: meshes.add(obstacle_shape.into()),
             material: materials.add(Color::rgb(0.0, 1.0, 0.5).into()),
             transform: Transform::from_translation(Vec3::new(-12.0, 0.0, 12.0)),
             ..default()
         },
+        AutoCollider::from(obstacle_shape),
+        CollisionLayers::new(LAYER_STATIC, CollisionLayers::ALL & !LAYER_STATIC),
+        BodyType::Static,
     ));
 
 }
@@ -146,9 +263,9 @@ pub fn controller(
     mut e_mouse_fire: EventWriter<MouseFire>,
     keyboard_input: Res<Input<KeyCode>>,
     mouse_input: Res<Input<MouseButton>>,
-    mut player_query: Query<&mut Velocity, With<Player>>,
+    mut player_query: Query<(&mut CharacterController, &mut PlayerJump), With<Player>>,
 ) {
-    let mut player_velocity = player_query.single_mut();
+    let (mut player_controller, mut player_jump) = player_query.single_mut();
     let mut direction = Vec3::ZERO;
 
     if keyboard_input.pressed(KeyCode::A) {
@@ -168,7 +285,14 @@ pub fn controller(
         direction = direction.normalize();
     }
 
-    player_velocity.vel = direction * CAMERA_SPEED;
+    //up and down is left to player_jump
+    let vertical_speed = player_controller.velocity.y;
+    player_controller.velocity = direction * CAMERA_SPEED;
+    player_controller.velocity.y = vertical_speed;
+
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        player_jump.press();
+    }
 
     if mouse_input.pressed(MouseButton::Left){
         e_mouse_fire.send(MouseFire);
@@ -178,11 +302,53 @@ pub fn controller(
 
 pub fn apply_vel (
     mut vel_and_transform: Query<(&Velocity, &mut Transform)>,
-    time: Res<Time>,
+    fixed_time: Res<FixedTime>,
 ) {
     for (vel, mut transform) in vel_and_transform.iter_mut() {
-        transform.translation += vel.vel * time.delta_seconds();
+        transform.translation += vel.vel * fixed_time.period.as_secs_f32();
+    }
+}
+
+pub fn player_jump (
+    mut q_player: Query<(&mut PlayerJump, &mut CharacterController), With<Player>>,
+    gravity: Res<Gravity>,
+    fixed_time: Res<FixedTime>,
+) {
+    let dt = fixed_time.period.as_secs_f32();
+    for (mut jump, mut controller) in q_player.iter_mut() {
+        controller.velocity.y = jump.before_move(controller.grounded, gravity.0.y, dt);
+    }
+}
+
+//the controller says when the player is on the ground or has hit their head, this keeps the jump in step with it
+pub fn player_landing (
+    mut q_player: Query<(&mut PlayerJump, &CharacterController), With<Player>>,
+    mut e_landed: EventWriter<PlayerLanded>,
+) {
+    for (mut jump, controller) in q_player.iter_mut() {
+        if let Some(speed) = jump.after_move(controller.grounded, controller.ceiling) {
+            e_landed.send(PlayerLanded { speed });
+        }
+    }
+}
+
+//knocks the camera down a little when the player lands so harder landings can be felt
+pub fn landing_dip (
+    mut e_landed: EventReader<PlayerLanded>,
+    mut q_camera: Query<&mut Transform, With<Camera3d>>,
+    time: Res<Time>,
+    mut dip: Local<f32>,
+) {
+    let Ok(mut camera) = q_camera.get_single_mut() else {
+        return;
+    };
+    //last frame's dip is undone first so the camera's offset from the player never drifts
+    camera.translation.y += *dip;
+    for landed in e_landed.iter() {
+        *dip = (*dip + landed.speed * LANDING_DIP_PER_SPEED).min(LANDING_DIP_MAX);
     }
+    *dip *= (-LANDING_DIP_RECOVERY * time.delta_seconds()).exp();
+    camera.translation.y -= *dip;
 }
 
 pub fn cursor_update (
@@ -191,6 +357,7 @@ pub fn cursor_update (
     q_window: Query<&Window, With<PrimaryWindow>>,
     q_camera: Query<(&Camera, &GlobalTransform), With<Camera>>,
     q_plane: Query<&GlobalTransform, With<GroundPlane>>,
+    spatial_query: SpatialQuery,
 ) {
     let (camera, camera_transform) = q_camera.single();
     let ground_transform = q_plane.single();
@@ -220,7 +387,13 @@ pub fn cursor_update (
     };
 
     //we can now get the position of the cursor from the distance it is down the ray
-    let global_cursor = ray.get_point(distance);
+    let mut global_cursor = ray.get_point(distance);
+
+    //anything with a collider in front of the ground (like the tops of the obstacles) gets picked instead
+    let filter = QueryFilter::from_mask(CollisionLayers::ALL & !LAYER_PLAYER & !LAYER_SPELL);
+    if let Some(hit) = spatial_query.cast_ray(ray.origin, ray.direction, distance, &filter) {
+        global_cursor = hit.point;
+    }
     r_cursor.pos = global_cursor;
 
     eprintln!("Global cursor coords: {}/{}/{}",
@@ -249,11 +422,12 @@ fn wand_aiming (
 
     //settings for current spell
     let sp = 50.0;
+    let spell_shape = shape::UVSphere { radius: 0.1, ..default() };
 
     for _fire in e_mouse_fire.iter() {
         commands.spawn((
             PbrBundle {
-                mesh: meshes.add(Mesh::from(shape::UVSphere { radius: 0.1, ..default() })),
+                mesh: meshes.add(Mesh::from(spell_shape)),
                 material: materials.add(Color::rgb(0.0, 0.1, 0.8).into()),
                 transform: Transform::from_translation(player_pos + (local_cursor_dir * 5.0)),
                 ..default()
@@ -267,7 +441,14 @@ fn wand_aiming (
             Velocity {
                 vel: local_cursor_dir.normalize() * sp,
             },
-            Collider::sphere_from_radius(0.1),
+            Interpolated::default(),
+            AutoCollider::from(spell_shape),
+            //spells move far enough in a frame to skip straight through things
+            Ccd::default(),
+            //moved by their velocity and never pushed around by what they hit
+            BodyType::Kinematic,
+            //spells shouldn't hit whoever cast them or each other
+            CollisionLayers::new(LAYER_SPELL, CollisionLayers::ALL & !LAYER_PLAYER & !LAYER_SPELL),
         ));
     }
 }
@@ -275,14 +456,18 @@ fn wand_aiming (
 pub fn spell_update (
     mut q_spells: Query<(Entity, &mut Spell, &mut Transform)>,
     mut commands: Commands,
-    time: Res<Time>,
+    mut e_collision: EventReader<CollisionStarted>,
+    fixed_time: Res<FixedTime>,
 ) {
+    //spells that ran into something this frame
+    let hits: Vec<Entity> = e_collision.iter().flat_map(|hit| [hit.e1, hit.e2]).collect();
+
     for (entity, mut spell, mut spell_transform) in q_spells.iter_mut() {
         //tick the spell's despawn timer
-        spell.ttl.tick(time.delta());
+        spell.ttl.tick(fixed_time.period);
 
-        //despawn if the timer's finished
-        if spell.ttl.finished() {
+        //despawn if the timer's finished or it has hit something
+        if spell.ttl.finished() || hits.contains(&entity) {
             commands.entity(entity).despawn();
             continue;
         }
@@ -293,4 +478,116 @@ pub fn spell_update (
         spell.speed += spell.acc * time.delta_seconds();
         */
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / PHYSICS_TICK_RATE;
+    const GRAVITY: f32 = -9.81;
+
+    //runs ticks in the air without landing and returns the last vertical speed
+    fn fall(jump: &mut PlayerJump, ticks: u32) -> f32 {
+        let mut speed = 0.0;
+        for _ in 0..ticks {
+            speed = jump.before_move(false, GRAVITY, DT);
+            jump.after_move(false, false);
+        }
+        return speed;
+    }
+
+    #[test]
+    fn coyote_time_after_walking_off_a_ledge() {
+        let mut jump = PlayerJump::default();
+        jump.before_move(true, GRAVITY, DT);
+        jump.after_move(true, false);
+
+        //a few ticks after the ground ran out is still close enough
+        fall(&mut jump, 3);
+        jump.press();
+        assert_eq!(jump.before_move(false, GRAVITY, DT), JUMP_SPEED);
+
+        //but not once coyote time has run out
+        let mut late = PlayerJump::default();
+        fall(&mut late, (COYOTE_TIME / DT) as u32 + 2);
+        late.press();
+        assert!(late.before_move(false, GRAVITY, DT) < 0.0);
+    }
+
+    #[test]
+    fn buffered_jump_fires_on_landing() {
+        let mut jump = PlayerJump::default();
+        fall(&mut jump, 30);
+        jump.press();
+        assert!(fall(&mut jump, 3) < 0.0, "jumped in the air");
+
+        assert!(jump.after_move(true, false).is_some());
+        assert_eq!(jump.before_move(true, GRAVITY, DT), JUMP_SPEED);
+    }
+
+    #[test]
+    fn buffered_jump_expires() {
+        let mut jump = PlayerJump::default();
+        fall(&mut jump, 30);
+        jump.press();
+        fall(&mut jump, (JUMP_BUFFER_TIME / DT) as u32 + 2);
+
+        jump.after_move(true, false);
+        assert_eq!(jump.before_move(true, GRAVITY, DT), 0.0);
+    }
+
+    #[test]
+    fn ceilings_stop_jumps() {
+        let mut jump = PlayerJump::default();
+        jump.press();
+        assert_eq!(jump.before_move(true, GRAVITY, DT), JUMP_SPEED);
+
+        jump.after_move(false, true);
+        assert!(jump.before_move(false, GRAVITY, DT) < 0.0, "kept going up into the ceiling");
+    }
+
+    #[test]
+    fn landing_sent_once() {
+        let mut app = App::new();
+        app.add_event::<PlayerLanded>()
+            .insert_resource(FixedTime::new_from_secs(DT))
+            .init_resource::<Gravity>()
+            .add_systems(FixedUpdate, (player_jump, player_landing).chain());
+        let player = app.world.spawn((Player, PlayerJump::default(), CharacterController::default())).id();
+
+        //the controller isn't running so whether the player is on the ground is set by hand
+        let run = |app: &mut App, grounded: bool, ticks: u32| {
+            for _ in 0..ticks {
+                app.world.get_mut::<CharacterController>(player).unwrap().grounded = grounded;
+                app.world.run_schedule(FixedUpdate);
+            }
+        };
+        run(&mut app, false, 20);
+        run(&mut app, true, 10);
+        assert_eq!(app.world.resource::<Events<PlayerLanded>>().len(), 1);
+
+        run(&mut app, false, 5);
+        run(&mut app, true, 5);
+        assert_eq!(app.world.resource::<Events<PlayerLanded>>().len(), 2);
+    }
+    #[test]
+    fn landing_dips_the_camera() {
+        let mut app = App::new();
+        app.add_event::<PlayerLanded>()
+            .init_resource::<Time>()
+            .add_systems(Update, landing_dip);
+        let camera = app.world.spawn((Camera3d::default(), Transform::from_xyz(0.0, 5.0, 0.0))).id();
+
+        app.world.send_event(PlayerLanded { speed: 10.0 });
+        app.update();
+        let dipped = app.world.get::<Transform>(camera).unwrap().translation.y;
+        assert!((dipped - (5.0 - 10.0 * LANDING_DIP_PER_SPEED)).abs() < 0.001, "camera at {}", dipped);
+
+        //a huge fall only goes as far as the cap, and without time passing the dip doesn't build up
+        app.world.send_event(PlayerLanded { speed: 1000.0 });
+        app.update();
+        app.update();
+        assert!((app.world.get::<Transform>(camera).unwrap().translation.y - (5.0 - LANDING_DIP_MAX)).abs() < 0.001);
+    }
+}